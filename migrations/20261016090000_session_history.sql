@@ -0,0 +1,37 @@
+-- Sessions are no longer deleted and reinserted on every update, instead
+-- every row keeps track of when it was seen on the court's website.
+CREATE TABLE sessions_history (
+    session_id INTEGER PRIMARY KEY NOT NULL,
+    court TEXT NOT NULL,
+    date TEXT NOT NULL, -- ISO8601 YYYY-MM-DD
+    time TEXT NOT NULL,
+    type TEXT NOT NULL,
+    lawsuit TEXT NOT NULL,
+    hall TEXT NOT NULL,
+    reference TEXT NOT NULL,
+    note TEXT NOT NULL,
+    first_seen TEXT NOT NULL, -- RFC3339 timestamp
+    last_seen TEXT NOT NULL, -- RFC3339 timestamp
+    removed_at TEXT -- RFC3339 timestamp, NULL while the session is listed
+);
+
+INSERT INTO sessions_history
+    (court, date, time, type, lawsuit, hall, reference, note, first_seen, last_seen)
+SELECT s.court, s.date, s.time, s.type, s.lawsuit, s.hall, s.reference, s.note,
+    c.last_update, c.last_update
+FROM sessions s JOIN courts c ON s.court = c.name;
+
+DROP TABLE sessions;
+ALTER TABLE sessions_history RENAME TO sessions;
+
+CREATE INDEX sessions_by_court ON sessions (court, removed_at);
+
+CREATE TABLE session_changes (
+    session_id INTEGER NOT NULL REFERENCES sessions (session_id),
+    changed_at TEXT NOT NULL, -- RFC3339 timestamp
+    field TEXT NOT NULL,
+    old_value TEXT NOT NULL,
+    new_value TEXT NOT NULL
+);
+
+CREATE INDEX session_changes_by_session ON session_changes (session_id);
@@ -0,0 +1,10 @@
+-- NULL: remind on the evening before, 0: no reminders,
+-- otherwise the number of minutes before the session starts
+ALTER TABLE subscriptions ADD COLUMN reminder_lead INTEGER;
+
+CREATE TABLE reminders_sent (
+    subscription_id INTEGER NOT NULL REFERENCES subscriptions (subscription_id) ON DELETE CASCADE,
+    session_id INTEGER NOT NULL REFERENCES sessions (session_id),
+    sent_at TEXT NOT NULL, -- RFC3339 timestamp
+    PRIMARY KEY (subscription_id, session_id)
+);
@@ -0,0 +1,19 @@
+CREATE TABLE chat_settings (
+    chat_id INTEGER PRIMARY KEY NOT NULL,
+    delivery TEXT NOT NULL DEFAULT 'immediate', -- 'immediate', 'daily' or 'weekly'
+    digest_time TEXT NOT NULL DEFAULT '08:00:00', -- local time in Europe/Berlin
+    digest_weekday INTEGER NOT NULL DEFAULT 0, -- 0 = Monday, only for weekly digests
+    last_digest TEXT -- RFC3339 timestamp
+);
+
+-- Changes waiting to be sent as part of a digest
+CREATE TABLE pending_changes (
+    pending_id INTEGER PRIMARY KEY NOT NULL,
+    chat_id INTEGER NOT NULL,
+    subscription_name TEXT NOT NULL,
+    court_name TEXT NOT NULL,
+    change TEXT NOT NULL, -- JSON
+    created_at TEXT NOT NULL -- RFC3339 timestamp
+);
+
+CREATE INDEX pending_changes_by_chat ON pending_changes (chat_id);
@@ -0,0 +1,4 @@
+-- Secret token for the subscription's iCalendar feed, NULL if there is none
+ALTER TABLE subscriptions ADD COLUMN feed_token TEXT;
+
+CREATE UNIQUE INDEX subscriptions_by_feed_token ON subscriptions (feed_token);
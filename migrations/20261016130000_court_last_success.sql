@@ -0,0 +1,5 @@
+-- Time of the last successful update. Unlike before, full_name is kept if
+-- an update fails.
+ALTER TABLE courts ADD COLUMN last_success TEXT; -- RFC3339 timestamp
+
+UPDATE courts SET last_success = last_update WHERE full_name IS NOT NULL;
@@ -0,0 +1,10 @@
+-- Validators of the pages retrieved for a court, used for conditional requests
+CREATE TABLE page_cache (
+    url TEXT PRIMARY KEY NOT NULL,
+    court TEXT NOT NULL,
+    etag TEXT,
+    last_modified TEXT,
+    content_hash TEXT NOT NULL
+);
+
+CREATE INDEX page_cache_court ON page_cache (court);
@@ -0,0 +1,4 @@
+-- Start and end time parsed from the free text in `time`. Existing rows are
+-- filled in on the next update of their court.
+ALTER TABLE sessions ADD COLUMN start_time TEXT;
+ALTER TABLE sessions ADD COLUMN end_time TEXT;
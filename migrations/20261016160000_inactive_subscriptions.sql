@@ -0,0 +1,5 @@
+-- Subscriptions of chats the bot can no longer send messages to, e.g.
+-- because it was blocked or removed from a group
+ALTER TABLE subscriptions ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE subscriptions ADD COLUMN inactive_reason TEXT; -- e.g. 'blocked', 'kicked', 'chat_not_found'
+ALTER TABLE subscriptions ADD COLUMN inactive_since TEXT; -- RFC3339 timestamp
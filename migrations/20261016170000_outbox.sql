@@ -0,0 +1,16 @@
+-- Notifications waiting to be sent, written in the same transaction as the
+-- session update they result from
+CREATE TABLE outbox (
+    outbox_id INTEGER PRIMARY KEY NOT NULL,
+    chat_id INTEGER NOT NULL,
+    subscription_name TEXT NOT NULL,
+    court_name TEXT NOT NULL,
+    changes TEXT NOT NULL, -- JSON list of changes
+    created_at TEXT NOT NULL, -- RFC3339 timestamp
+    attempts INTEGER NOT NULL DEFAULT 0, -- failed attempts so far
+    next_attempt TEXT NOT NULL, -- RFC3339 timestamp
+    sent_messages INTEGER NOT NULL DEFAULT 0, -- messages of the chain already sent
+    reply_to INTEGER -- id of the first message of the chain, once sent
+);
+
+CREATE INDEX outbox_by_chat ON outbox (chat_id);
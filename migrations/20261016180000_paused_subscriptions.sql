@@ -0,0 +1,2 @@
+-- Paused subscriptions get neither notifications nor reminders
+ALTER TABLE subscriptions ADD COLUMN paused INTEGER NOT NULL DEFAULT 0;
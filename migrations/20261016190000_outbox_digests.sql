@@ -0,0 +1,3 @@
+-- digests are sent through the outbox as well, their `changes` are a JSON list
+-- of pending changes and the subscription and court names are empty
+ALTER TABLE outbox ADD COLUMN digest INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- reminders are sent through the outbox too, so the flag becomes a kind
+ALTER TABLE outbox ADD COLUMN kind TEXT NOT NULL DEFAULT 'changes'; -- 'changes', 'digest' or 'reminder'
+UPDATE outbox SET kind = 'digest' WHERE digest != 0;
+ALTER TABLE outbox DROP COLUMN digest;
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::reference::ReferenceFilter;
+use crate::scraper::{Session, SessionKey};
+
+/// How a session of a previous scrape relates to a session of a new scrape.
+/// The indices refer to the old and new session lists, respectively.
+#[derive(Debug, Clone, Copy)]
+pub enum SessionMatch {
+    Changed(usize, usize),
+    Added(usize),
+    Removed(usize),
+}
+
+/// Matches sessions by their [`SessionKey`]. Unchanged sessions are omitted.
+pub fn match_sessions(old_sessions: &[Session], new_sessions: &[Session]) -> Vec<SessionMatch> {
+    let mut by_key: HashMap<SessionKey, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    for (index, session) in old_sessions.iter().enumerate() {
+        by_key.entry(session.key()).or_default().0.push(index);
+    }
+    for (index, session) in new_sessions.iter().enumerate() {
+        by_key.entry(session.key()).or_default().1.push(index);
+    }
+
+    let mut matches = vec![];
+
+    for (mut old, mut new) in by_key.into_values() {
+        // unchanged sessions don't need to be paired up
+        old.retain(
+            |&o| match new.iter().position(|&n| new_sessions[n] == old_sessions[o]) {
+                Some(position) => {
+                    new.remove(position);
+                    false
+                }
+                None => true,
+            },
+        );
+
+        let mut old = old.into_iter();
+        let mut new = new.into_iter();
+        loop {
+            match (old.next(), new.next()) {
+                (Some(o), Some(n)) => matches.push(SessionMatch::Changed(o, n)),
+                (Some(o), None) => matches.push(SessionMatch::Removed(o)),
+                (None, Some(n)) => matches.push(SessionMatch::Added(n)),
+                (None, None) => break,
+            }
+        }
+    }
+
+    matches
+}
+
+/// A single change that is relevant to a subscriber.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SessionChange {
+    Added(Session),
+    Changed { old: Session, new: Session },
+    Cancelled(Session),
+}
+
+/// Differences between two scrapes of the same court.
+#[derive(Debug, Clone, Default)]
+pub struct SessionChanges {
+    pub added: Vec<Session>,
+    /// Pairs of `(old, new)` sessions with the same identity but different details.
+    pub changed: Vec<(Session, Session)>,
+    pub cancelled: Vec<Session>,
+}
+
+impl SessionChanges {
+    /// Sessions that vanished from the listing only count as cancelled if
+    /// they were scheduled for `today` or later, older ones are simply past.
+    pub fn from_matches(
+        matches: &[SessionMatch],
+        old_sessions: &[Session],
+        new_sessions: &[Session],
+        today: NaiveDate,
+    ) -> Self {
+        let mut added = vec![];
+        let mut changed = vec![];
+        let mut cancelled = vec![];
+
+        for m in matches {
+            match *m {
+                SessionMatch::Changed(o, n) => changed.push((o, n)),
+                SessionMatch::Added(n) => added.push(n),
+                SessionMatch::Removed(o) => {
+                    if old_sessions[o].date >= today {
+                        cancelled.push(o)
+                    }
+                }
+            }
+        }
+
+        // keep the order of the listing
+        added.sort();
+        changed.sort_by_key(|&(_, n)| n);
+        cancelled.sort();
+
+        Self {
+            added: added.into_iter().map(|n| new_sessions[n].clone()).collect(),
+            changed: changed
+                .into_iter()
+                .map(|(o, n)| (old_sessions[o].clone(), new_sessions[n].clone()))
+                .collect(),
+            cancelled: cancelled
+                .into_iter()
+                .map(|o| old_sessions[o].clone())
+                .collect(),
+        }
+    }
+
+    /// Returns the changes concerning sessions with a matching reference.
+    pub fn matching(&self, reference: &ReferenceFilter) -> Vec<SessionChange> {
+        let added = self
+            .added
+            .iter()
+            .filter(|session| reference.matches(&session.reference))
+            .map(|session| SessionChange::Added(session.clone()));
+
+        let changed = self
+            .changed
+            .iter()
+            .filter(|(_, session)| reference.matches(&session.reference))
+            .map(|(old, new)| SessionChange::Changed {
+                old: old.clone(),
+                new: new.clone(),
+            });
+
+        let cancelled = self
+            .cancelled
+            .iter()
+            .filter(|session| reference.matches(&session.reference))
+            .map(|session| SessionChange::Cancelled(session.clone()));
+
+        added.chain(changed).chain(cancelled).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 9, day).unwrap()
+    }
+
+    fn session(day: u32, reference: &str, time: &str, hall: &str) -> Session {
+        Session {
+            date: date(day),
+            time: time.to_string(),
+            start_time: None,
+            end_time: None,
+            r#type: "Verhandlung".to_string(),
+            lawsuit: "A ./. B".to_string(),
+            hall: hall.to_string(),
+            reference: reference.to_string(),
+            note: String::new(),
+        }
+    }
+
+    fn changes(old: &[Session], new: &[Session], today: NaiveDate) -> SessionChanges {
+        SessionChanges::from_matches(&match_sessions(old, new), old, new, today)
+    }
+
+    #[test]
+    fn unchanged() {
+        let sessions = [
+            session(2, "1 O 1/24", "09:30", "A 101"),
+            session(3, "1 O 2/24", "10:00", "A 102"),
+        ];
+        assert!(match_sessions(&sessions, &sessions).is_empty());
+    }
+
+    #[test]
+    fn changed_time_and_hall() {
+        let old = [session(2, "1 O 1/24", "09:30", "A 101")];
+        let new = [session(2, "1 O 1/24", "11:00", "B 201")];
+
+        let matches = match_sessions(&old, &new);
+        assert!(matches!(matches[..], [SessionMatch::Changed(0, 0)]));
+
+        let changes = changes(&old, &new, date(1));
+        assert_eq!(changes.changed, vec![(old[0].clone(), new[0].clone())]);
+        assert!(changes.added.is_empty());
+        assert!(changes.cancelled.is_empty());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        // two sessions of the same lawsuit on one day, only one of them moves
+        let old = [
+            session(2, "1 O 1/24", "09:30", "A 101"),
+            session(2, "1 O 1/24", "14:00", "A 101"),
+        ];
+        let new = [
+            session(2, "1 O 1/24", "09:30", "A 101"),
+            session(2, "1 O 1/24", "15:00", "A 101"),
+        ];
+
+        let changes = changes(&old, &new, date(1));
+        assert_eq!(changes.changed, vec![(old[1].clone(), new[1].clone())]);
+        assert!(changes.added.is_empty());
+        assert!(changes.cancelled.is_empty());
+
+        // a third one is added
+        let mut more = new.to_vec();
+        more.push(session(2, "1 O 1/24", "16:00", "A 101"));
+        let changes =
+            SessionChanges::from_matches(&match_sessions(&new, &more), &new, &more, date(1));
+        assert_eq!(changes.added, vec![more[2].clone()]);
+        assert!(changes.changed.is_empty());
+    }
+
+    #[test]
+    fn removed_sessions() {
+        let old = [
+            session(1, "1 O 1/24", "09:30", "A 101"),
+            session(2, "1 O 2/24", "09:30", "A 101"),
+            session(3, "1 O 3/24", "09:30", "A 101"),
+        ];
+
+        let changes = changes(&old, &[], date(2));
+        // the session of the 1st is past and no cancellation
+        assert_eq!(changes.cancelled, old[1..].to_vec());
+        assert!(changes.added.is_empty());
+        assert!(changes.changed.is_empty());
+    }
+
+    #[test]
+    fn matching_reference() {
+        let old = [session(2, "1 O 1/24", "09:30", "A 101")];
+        let new = [
+            session(2, "1 O 1/24", "11:00", "A 101"),
+            session(3, "2 O 5/24", "09:30", "A 101"),
+        ];
+        let changes = changes(&old, &new, date(1));
+
+        let matching = changes.matching(&ReferenceFilter::new("1 O *"));
+        assert!(matches!(matching[..], [SessionChange::Changed { .. }]));
+        let matching = changes.matching(&ReferenceFilter::new("2 O *"));
+        assert!(matches!(matching[..], [SessionChange::Added(_)]));
+    }
+}
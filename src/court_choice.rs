@@ -0,0 +1,93 @@
+//! Lets users pick the court from an inline keyboard if the court name in a
+//! command is ambiguous. The command is kept here until a button is pressed.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, Message};
+use tokio::sync::Mutex;
+
+use crate::directory::Candidate;
+use crate::Command;
+
+/// How long the buttons stay usable
+const CHOICE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const CALLBACK_PREFIX: &str = "court:";
+/// Above this, the user is asked to be more specific instead
+pub const MAX_CHOICES: usize = 8;
+
+struct PendingChoice {
+    msg: Message,
+    cmd: Command,
+    candidates: Vec<Candidate>,
+    created: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    pending: HashMap<u64, PendingChoice>,
+}
+
+#[derive(Clone, Default)]
+pub struct CourtChoices(Arc<Mutex<Inner>>);
+
+pub fn is_court_choice(callback_data: &str) -> bool {
+    callback_data.starts_with(CALLBACK_PREFIX)
+}
+
+impl CourtChoices {
+    /// Keeps `cmd` until a court is chosen and returns the keyboard to choose
+    /// it with.
+    pub async fn add(
+        &self,
+        msg: Message,
+        cmd: Command,
+        candidates: Vec<Candidate>,
+    ) -> InlineKeyboardMarkup {
+        let mut inner = self.0.lock().await;
+        inner
+            .pending
+            .retain(|_, choice| choice.created.elapsed() < CHOICE_TIMEOUT);
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+
+        let buttons = candidates.iter().enumerate().map(|(index, candidate)| {
+            let data = format!("{CALLBACK_PREFIX}{id}:{index}");
+            [InlineKeyboardButton::callback(&candidate.full_name, data)]
+        });
+        let keyboard = InlineKeyboardMarkup::new(buttons);
+
+        inner.pending.insert(
+            id,
+            PendingChoice {
+                msg,
+                cmd,
+                candidates,
+                created: Instant::now(),
+            },
+        );
+
+        keyboard
+    }
+
+    /// Returns the original message and command together with the chosen
+    /// court, or `None` if the choice expired or was already made.
+    pub async fn take(&self, callback_data: &str) -> Option<(Message, Command, Candidate)> {
+        let (id, index) = callback_data
+            .strip_prefix(CALLBACK_PREFIX)?
+            .split_once(':')?;
+        let (id, index): (u64, usize) = (id.parse().ok()?, index.parse().ok()?);
+
+        let mut inner = self.0.lock().await;
+        let choice = inner.pending.remove(&id)?;
+        if choice.created.elapsed() >= CHOICE_TIMEOUT {
+            return None;
+        }
+
+        let candidate = choice.candidates.into_iter().nth(index)?;
+        Some((choice.msg, choice.cmd, candidate))
+    }
+}
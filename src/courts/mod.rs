@@ -1,30 +1,38 @@
 mod worker;
 
+pub use worker::MAX_REMINDER_LEAD_DAYS;
+
 use std::collections::HashMap;
 use std::future::Future;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::Duration;
 
 use futures_core::future::BoxFuture;
-use lazy_static::lazy_static;
-use regex::Regex;
+use teloxide::types::ChatId;
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tokio::time::{interval_at, Instant, MissedTickBehavior};
 
 use crate::database::Database;
 use crate::messages::MarkdownString;
+use crate::scraper::{self, CourtSource};
 use crate::Bot;
 
 enum Message {
     Update {
         force: bool,
+        /// Accept the scrape even if all upcoming sessions disappeared
+        accept_drop: bool,
     },
     GetSessions {
         date: String,
         reference: String,
         reply_fn: Box<dyn ReplyFn>,
     },
+    GetCalendar {
+        reference: String,
+        reply_fn: Box<dyn ReplyFn<CalendarReply>>,
+    },
     ConfirmSubscription {
         subscription_id: i64,
         reply_fn: Box<dyn ReplyFn>,
@@ -42,31 +50,36 @@ impl Drop for Court {
     }
 }
 
-pub trait ReplyFn: Send + 'static {
-    fn reply(self: Box<Self>, msgs: Vec<MarkdownString>) -> BoxFuture<'static, ()>;
+pub trait ReplyFn<R = Vec<MarkdownString>>: Send + 'static {
+    fn reply(self: Box<Self>, msgs: R) -> BoxFuture<'static, ()>;
 }
 
-impl<T, F: Future<Output = ()> + Send + 'static> ReplyFn for T
+impl<R, T, F: Future<Output = ()> + Send + 'static> ReplyFn<R> for T
 where
-    T: (FnOnce(Vec<MarkdownString>) -> F) + Send + 'static,
+    T: (FnOnce(R) -> F) + Send + 'static,
 {
-    fn reply(self: Box<Self>, msg: Vec<MarkdownString>) -> BoxFuture<'static, ()> {
+    fn reply(self: Box<Self>, msg: R) -> BoxFuture<'static, ()> {
         Box::pin(self(msg)) as BoxFuture<'static, ()>
     }
 }
 
+/// Either the content of an iCalendar file or an error message
+pub type CalendarReply = Result<String, Vec<MarkdownString>>;
+
 pub struct Courts {
     map: HashMap<String, Court>,
     bot: Bot,
     database: Database,
+    admin_chat: Option<ChatId>,
 }
 
 impl Courts {
-    pub async fn new(bot: Bot, database: Database) -> Self {
+    pub async fn new(bot: Bot, database: Database, admin_chat: Option<ChatId>) -> Self {
         let mut this = Self {
             bot,
             map: Default::default(),
             database,
+            admin_chat,
         };
 
         this.init_subscribed_courts().await;
@@ -74,6 +87,10 @@ impl Courts {
         this
     }
 
+    pub fn is_admin_chat(&self, chat_id: ChatId) -> bool {
+        self.admin_chat == Some(chat_id)
+    }
+
     async fn init_subscribed_courts(&mut self) {
         match self.database.get_subscribed_courts().await {
             Ok(names) => {
@@ -91,12 +108,14 @@ impl Courts {
     }
 
     pub fn get<'a>(&'a mut self, court_name: &'a str) -> Result<CourtRef<'a>, InvalidCourtName> {
-        if !COURT_NAME_REGEX.is_match(court_name) {
+        let Some((source, source_court)) = scraper::lookup(court_name) else {
             return Err(InvalidCourtName(()));
-        }
+        };
         Ok(CourtRef {
             courts: self,
             name: court_name,
+            source,
+            source_court,
         })
     }
 }
@@ -105,13 +124,13 @@ impl Courts {
 #[error("invalid court name")]
 pub struct InvalidCourtName(());
 
-lazy_static! {
-    static ref COURT_NAME_REGEX: Regex = Regex::new("^[a-zA-Z0-9\\-]{1,63}$").unwrap();
-}
+const REMINDER_PERIOD: Duration = Duration::from_secs(60);
 
 pub struct CourtRef<'a> {
     courts: &'a mut Courts,
     name: &'a str,
+    source: &'static dyn CourtSource,
+    source_court: &'a str,
 }
 
 impl<'a> CourtRef<'a> {
@@ -127,15 +146,23 @@ impl<'a> CourtRef<'a> {
         let mut auto_update = interval_at(Instant::now() + period, period);
         auto_update.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+        let mut reminders = interval_at(Instant::now() + REMINDER_PERIOD, REMINDER_PERIOD);
+        reminders.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
         let name = self.name.to_string();
         let bot = self.courts.bot.clone();
         let database = self.courts.database.clone();
         let worker = worker::CourtWorker {
             name,
+            source: self.source,
+            source_court: self.source_court.to_string(),
             message_rx,
             bot,
             auto_update,
+            reminders,
             database,
+            admin_chat: self.courts.admin_chat,
+            alert_sent: false,
         };
 
         tokio::spawn(worker.run());
@@ -149,6 +176,11 @@ impl<'a> CourtRef<'a> {
         }
     }
 
+    /// Whether a worker was started for the court, which keeps it up to date
+    pub fn is_running(&self) -> bool {
+        self.courts.map.contains_key(self.name)
+    }
+
     fn send_msg(&mut self, mut msg: Message) {
         if let Some(court) = self.courts.map.get(self.name) {
             match court.message_tx.send(msg) {
@@ -180,6 +212,13 @@ impl<'a> CourtRef<'a> {
         })
     }
 
+    pub fn get_calendar(&mut self, reference: String, reply_fn: impl ReplyFn<CalendarReply>) {
+        self.send_msg(Message::GetCalendar {
+            reference,
+            reply_fn: Box::new(reply_fn),
+        })
+    }
+
     pub fn confirm_subscription(&mut self, subscription_id: i64, reply_fn: impl ReplyFn) {
         self.send_msg(Message::ConfirmSubscription {
             subscription_id,
@@ -188,6 +227,18 @@ impl<'a> CourtRef<'a> {
     }
 
     pub fn update(&mut self, force: bool) {
-        self.send_msg(Message::Update { force })
+        self.send_msg(Message::Update {
+            force,
+            accept_drop: false,
+        })
+    }
+
+    /// Forces an update that is accepted even if all upcoming sessions
+    /// disappeared, for courts that really have no sessions anymore.
+    pub fn accept_drop(&mut self) {
+        self.send_msg(Message::Update {
+            force: true,
+            accept_drop: true,
+        })
     }
 }
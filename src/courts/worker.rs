@@ -1,14 +1,20 @@
-use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeDelta, Utc};
 use teloxide::types::ChatId;
 use tokio::sync::mpsc;
 
-use super::Message;
-use crate::database::{CourtMeta, Database, Error as DbError};
+use super::{CalendarReply, Message};
+use crate::database::{CachedCourtData, CourtMeta, Database, Error as DbError, Reminder};
 use crate::messages::MarkdownString;
-use crate::scraper::CourtData;
-use crate::{messages, scraper, send_chain, Bot};
+use crate::reference::ReferenceFilter;
+use crate::scraper::{
+    CourtData, CourtScrape, CourtSource, Error as ScrapeError, PageCache, Session,
+};
+use crate::{ical, messages, send_chain, Bot};
 
-pub const TRESHOLD_TIME: NaiveTime = NaiveTime::from_hms(8, 0, 0);
+pub const TRESHOLD_TIME: NaiveTime = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+pub const REMINDER_EVENING_TIME: NaiveTime = NaiveTime::from_hms_opt(18, 0, 0).unwrap();
+/// Upper bound for [`Reminder::MinutesBefore`]
+pub const MAX_REMINDER_LEAD_DAYS: u64 = 7;
 
 fn is_out_of_date(last_update: DateTime<Utc>) -> bool {
     let now = Utc::now().with_timezone(&chrono_tz::Europe::Berlin);
@@ -31,12 +37,70 @@ fn is_out_of_date(last_update: DateTime<Utc>) -> bool {
     last_update < treshold
 }
 
+fn berlin_datetime(date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    date.and_time(time)
+        .and_local_timezone(chrono_tz::Europe::Berlin)
+        .earliest()
+        .map(|t| t.to_utc())
+}
+
+/// Returns whether a reminder for `session` is due at `now`. Reminders are
+/// not sent anymore once the session has started.
+fn is_reminder_due(reminder: Reminder, session: &Session, now: DateTime<Utc>) -> bool {
+    let start_time = session.start_time;
+    let start = berlin_datetime(session.date, start_time.unwrap_or(NaiveTime::MIN));
+
+    let due = match (reminder, start_time) {
+        (Reminder::Off, _) => return false,
+        (Reminder::MinutesBefore(minutes), Some(_)) => {
+            start.map(|start| start - TimeDelta::minutes(minutes))
+        }
+        // without a known start time, fall back to the evening before
+        (Reminder::EveningBefore, _) | (Reminder::MinutesBefore(_), None) => session
+            .date
+            .checked_sub_days(Days::new(1))
+            .and_then(|date| berlin_datetime(date, REMINDER_EVENING_TIME)),
+    };
+
+    let Some(due) = due else {
+        return false;
+    };
+
+    match start_time {
+        Some(_) => due <= now && start.is_some_and(|start| now < start),
+        None => {
+            due <= now && now.with_timezone(&chrono_tz::Europe::Berlin).date_naive() <= session.date
+        }
+    }
+}
+
+/// How long to wait before retrying after the website could not be reached
+const FAILED_UPDATE_RETRY: TimeDelta = TimeDelta::minutes(15);
+
+/// Below this many upcoming sessions, a court running out of sessions is
+/// plausible
+const MIN_SUSPICIOUS_DROP: usize = 3;
+
+fn needs_update(meta: &CourtMeta) -> bool {
+    match meta.last_success {
+        Some(last_success) if !meta.last_update_failed() => is_out_of_date(last_success),
+        _ => Utc::now() - meta.last_update >= FAILED_UPDATE_RETRY,
+    }
+}
+
 pub struct CourtWorker {
     pub name: String,
+    pub source: &'static dyn CourtSource,
+    /// Court id as understood by `source`
+    pub source_court: String,
     pub message_rx: mpsc::UnboundedReceiver<Message>,
     pub auto_update: tokio::time::Interval,
+    pub reminders: tokio::time::Interval,
     pub bot: Bot,
     pub database: Database,
+    /// Chat that is alerted if the website can't be understood anymore
+    pub admin_chat: Option<ChatId>,
+    pub alert_sent: bool,
 }
 
 macro_rules! handle_db_error {
@@ -52,58 +116,124 @@ macro_rules! handle_db_error {
 }
 
 impl CourtWorker {
-    async fn process_new_data(&self, new_data: &CourtData) -> Result<(), DbError> {
-        let old_sessions = self.database.get_sessions(&self.name, None).await?;
-        let subscriptions = self
-            .database
-            .get_confirmed_subscriptions_by_court(&self.name)
-            .await?;
+    /// Whether all upcoming sessions vanished at once, which rather indicates
+    /// a changed website than a court without sessions.
+    async fn is_suspicious_drop(
+        &self,
+        scrape: &CourtScrape,
+        today: NaiveDate,
+    ) -> Result<bool, DbError> {
+        if !scrape.data.sessions.is_empty()
+            || !scrape.unchanged_dates.is_empty()
+            || scrape.is_partial()
+        {
+            return Ok(false);
+        }
 
-        for sub in subscriptions {
-            let msgs = messages::sessions_updated(
-                &old_sessions,
-                &new_data.sessions,
-                &new_data.full_name,
-                &sub.name,
-                &sub.reference_filter,
-            );
+        let sessions = self.database.get_sessions(&self.name, None).await?;
+        let upcoming = sessions.iter().filter(|s| s.date >= today).count();
+        Ok(upcoming >= MIN_SUSPICIOUS_DROP)
+    }
 
-            send_chain(&self.bot, ChatId(sub.chat_id), msgs).await
+    /// Alerts the admin chat, once until the website can be read again.
+    async fn alert_admin(&mut self, error: &ScrapeError) {
+        if self.alert_sent {
+            return;
         }
+        self.alert_sent = true;
 
-        Ok(())
+        log::error!("{}: Website changed? {error}", self.name);
+        if let Some(admin_chat) = self.admin_chat {
+            let msg = messages::scraper_alert(&self.name, &error.to_string());
+            send_chain(&self.bot, &self.database, admin_chat, vec![msg]).await
+        }
     }
 
-    async fn update(&mut self, force_update: bool) -> Result<CourtMeta, DbError> {
+    async fn resolve_alert(&mut self) {
+        if !self.alert_sent {
+            return;
+        }
+        self.alert_sent = false;
+
+        log::info!("{}: Website can be read again", self.name);
+        if let Some(admin_chat) = self.admin_chat {
+            let msg = messages::scraper_alert_resolved(&self.name);
+            send_chain(&self.bot, &self.database, admin_chat, vec![msg]).await
+        }
+    }
+
+    async fn update(
+        &mut self,
+        force_update: bool,
+        accept_drop: bool,
+    ) -> Result<CourtMeta, DbError> {
         log::debug!("{}: Checking for update", self.name);
 
-        if let Some(meta) = self.database.get_court_meta(&self.name).await? {
-            if !force_update && !is_out_of_date(meta.last_update) {
+        let old_meta = self.database.get_court_meta(&self.name).await?;
+        if let Some(meta) = &old_meta {
+            if !force_update && !needs_update(meta) {
                 log::debug!("{}: Already up to date", self.name);
-                return Ok(meta);
+                return Ok(meta.clone());
             }
         }
 
         log::info!("{}: Out of date, updating", self.name);
 
-        let last_update = Utc::now(); // Better have last_update too old than too new
-        let new_data = scraper::get_court_data(&self.name)
-            .await
-            .map_err(|e| log::warn!("Failed to get info for court {}: {e}", &self.name))
-            .ok();
+        let cache = if force_update {
+            PageCache::new()
+        } else {
+            self.database.get_page_cache(&self.name).await?
+        };
 
-        if let Some(new_data) = &new_data {
-            self.process_new_data(new_data).await?;
+        let last_update = Utc::now(); // Better have last_update too old than too new
+        let today = last_update
+            .with_timezone(&chrono_tz::Europe::Berlin)
+            .date_naive();
+
+        let mut result = self.source.get_court_data(&self.source_court, &cache).await;
+        if let Ok(scrape) = &result {
+            if !accept_drop && self.is_suspicious_drop(scrape, today).await? {
+                result = Err(ScrapeError::Parse(
+                    "all upcoming sessions disappeared, /force_update in the admin chat accepts this"
+                        .into(),
+                ));
+            }
         }
 
-        let sessions = new_data.as_ref().map(|x| &x.sessions[..]);
-        let meta = CourtMeta {
-            last_update,
-            full_name: new_data.as_ref().map(|x| x.full_name.clone()),
+        let scrape = match result {
+            Ok(scrape) => {
+                self.resolve_alert().await;
+                Some(scrape)
+            }
+            Err(e) => {
+                log::warn!("Failed to get info for court {}: {e}", &self.name);
+                if e.is_content_error() {
+                    self.alert_admin(&e).await;
+                }
+                None
+            }
         };
 
+        let meta = match (&scrape, old_meta) {
+            (Some(scrape), _) => CourtMeta {
+                full_name: Some(scrape.data.full_name.clone()),
+                last_update,
+                last_success: Some(last_update),
+            },
+            // keep the data of the last successful update
+            (None, Some(old_meta)) => CourtMeta {
+                last_update,
+                ..old_meta
+            },
+            (None, None) => CourtMeta {
+                full_name: None,
+                last_update,
+                last_success: None,
+            },
+        };
+        // the notifications are sent from the outbox
         self.database
-            .update_court_data(&self.name, &meta, sessions)
+            .update_court_data(&self.name, &meta, scrape.as_ref(), today)
             .await?;
 
         log::info!("Court {} has been updated", self.name);
@@ -111,33 +241,89 @@ impl CourtWorker {
         Ok(meta)
     }
 
+    async fn send_reminders(&self) -> Result<(), DbError> {
+        let now = Utc::now();
+        let today = now.with_timezone(&chrono_tz::Europe::Berlin).date_naive();
+        let until = today + Days::new(MAX_REMINDER_LEAD_DAYS + 1);
+
+        let sessions = self
+            .database
+            .get_upcoming_sessions(&self.name, today, until)
+            .await?;
+        if sessions.is_empty() {
+            return Ok(());
+        }
+
+        let subscriptions = self
+            .database
+            .get_confirmed_subscriptions_by_court(&self.name)
+            .await?;
+        let full_name = self
+            .database
+            .get_court_meta(&self.name)
+            .await?
+            .and_then(|meta| meta.full_name)
+            .unwrap_or_else(|| self.name.clone());
+
+        for sub in subscriptions {
+            let reminder = sub.reminder();
+            let reference = ReferenceFilter::new(&sub.reference_filter);
+
+            for stored in &sessions {
+                let session = &stored.session;
+                if !reference.matches(&session.reference)
+                    || !is_reminder_due(reminder, session, now)
+                {
+                    continue;
+                }
+
+                // sent from the outbox, which retries failures
+                self.database
+                    .queue_reminder(&sub, stored, &full_name, now)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn get_court_data(
         &mut self,
         date_filter: Option<NaiveDate>,
-    ) -> Result<Option<CourtData>, DbError> {
-        let meta = self.update(false).await?;
+    ) -> Result<Option<CachedCourtData>, DbError> {
+        let meta = self.update(false, false).await?;
+        let stale_since = meta
+            .last_update_failed()
+            .then_some(meta.last_success)
+            .flatten();
 
         let Some(full_name) = meta.full_name else {
-            // if full_name is None, the website was not available
+            // if full_name is None, the website was never available
             return Ok(None);
         };
 
         let sessions = self.database.get_sessions(&self.name, date_filter).await?;
 
-        let court_data = CourtData {
+        let data = CourtData {
             full_name,
             sessions,
         };
 
-        Ok(Some(court_data))
+        Ok(Some(CachedCourtData { data, stale_since }))
     }
 
-    async fn handle_update(&mut self, force_update: bool) {
-        if let Err(e) = self.update(force_update).await {
+    async fn handle_update(&mut self, force_update: bool, accept_drop: bool) {
+        if let Err(e) = self.update(force_update, accept_drop).await {
             log::error!("Update failed: {e}")
         }
     }
 
+    async fn handle_reminders(&mut self) {
+        if let Err(e) = self.send_reminders().await {
+            log::error!("Sending reminders failed: {e}")
+        }
+    }
+
     async fn handle_get_sessions(
         &mut self,
         date: String,
@@ -157,6 +343,36 @@ impl CourtWorker {
         messages::list_sessions(&data, &reference)
     }
 
+    async fn handle_get_calendar(&mut self, reference: String) -> CalendarReply {
+        let data = match self.get_court_data(None).await {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Database error: {e}");
+                return Err(vec![messages::internal_error()]);
+            }
+        };
+
+        let Some(data) = data else {
+            return Err(messages::list_sessions(&None, &reference));
+        };
+
+        let filter = ReferenceFilter::new(&reference);
+        let sessions: Vec<_> = data
+            .data
+            .sessions
+            .iter()
+            .filter(|s| filter.matches(&s.reference))
+            .collect();
+
+        if sessions.is_empty() {
+            return Err(messages::list_sessions(&Some(data), &reference));
+        }
+
+        let full_name = &data.data.full_name;
+        let title = format!("{full_name}: {reference}");
+        Ok(ical::calendar(&self.name, full_name, &title, sessions))
+    }
+
     async fn handle_confirm_subscription(&mut self, subscription_id: i64) -> Vec<MarkdownString> {
         let sub = handle_db_error!(self.database.get_subscription_by_id(subscription_id).await);
 
@@ -181,14 +397,17 @@ impl CourtWorker {
         log::info!("Starting worker task for {}", self.name);
         loop {
             tokio::select! {
-                _ = self.auto_update.tick() => self.handle_update(false).await,
+                _ = self.auto_update.tick() => self.handle_update(false, false).await,
+                _ = self.reminders.tick() => self.handle_reminders().await,
                 msg = self.message_rx.recv() => {
                     let Some(msg) = msg else {
                         // channel closed, no more messages
                         break
                     };
                     match msg {
-                        Message::Update { force } => self.handle_update(force).await,
+                        Message::Update { force, accept_drop } => {
+                            self.handle_update(force, accept_drop).await
+                        }
                         Message::GetSessions {
                             date,
                             reference,
@@ -197,6 +416,13 @@ impl CourtWorker {
                             let reply = self.handle_get_sessions(date, reference).await;
                             reply_fn.reply(reply).await;
                         }
+                        Message::GetCalendar {
+                            reference,
+                            reply_fn
+                        } => {
+                            let reply = self.handle_get_calendar(reference).await;
+                            reply_fn.reply(reply).await;
+                        }
                         Message::ConfirmSubscription {
                             subscription_id,
                             reply_fn
@@ -214,3 +440,85 @@ impl CourtWorker {
         log::info!("Worker task for {} shut down.", self.name);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn berlin(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // the clocks are put forward on the night to 2024-03-31
+        let date = NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        berlin_datetime(date, NaiveTime::from_hms_opt(hour, minute, 0).unwrap()).unwrap()
+    }
+
+    fn session(start_time: Option<NaiveTime>) -> Session {
+        Session {
+            date: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            time: String::new(),
+            start_time,
+            end_time: None,
+            r#type: String::new(),
+            lawsuit: String::new(),
+            hall: String::new(),
+            reference: "1 K 1/24".to_string(),
+            note: String::new(),
+        }
+    }
+
+    fn ten() -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(10, 0, 0)
+    }
+
+    #[test]
+    fn evening_before() {
+        let session = session(ten());
+        let due = |now| is_reminder_due(Reminder::EveningBefore, &session, now);
+        assert!(!due(berlin(30, 17, 59)));
+        assert!(due(berlin(30, 18, 0)));
+        assert!(due(berlin(31, 9, 59)));
+        // not after the start
+        assert!(!due(berlin(31, 10, 0)));
+        assert!(!due(berlin(31, 11, 0)));
+    }
+
+    #[test]
+    fn evening_before_without_start_time() {
+        let session = session(None);
+        let due = |now| is_reminder_due(Reminder::EveningBefore, &session, now);
+        assert!(!due(berlin(30, 17, 59)));
+        assert!(due(berlin(30, 18, 0)));
+        // the whole day of the session, as it may start anytime
+        assert!(due(berlin(31, 23, 0)));
+        assert!(!due(berlin(31, 23, 0) + TimeDelta::hours(1)));
+    }
+
+    #[test]
+    fn minutes_before() {
+        let session = session(ten());
+        let due = |now| is_reminder_due(Reminder::MinutesBefore(30), &session, now);
+        assert!(!due(berlin(30, 18, 0)));
+        assert!(!due(berlin(31, 9, 29)));
+        assert!(due(berlin(31, 9, 30)));
+        assert!(!due(berlin(31, 10, 0)));
+
+        // across the change to summer time, 10:00 CEST is 23:00 CET
+        let due = |now| is_reminder_due(Reminder::MinutesBefore(600), &session, now);
+        assert!(!due(berlin(30, 22, 59)));
+        assert!(due(berlin(30, 23, 0)));
+    }
+
+    #[test]
+    fn minutes_before_without_start_time() {
+        let session = session(None);
+        let due = |now| is_reminder_due(Reminder::MinutesBefore(30), &session, now);
+        assert!(!due(berlin(30, 17, 59)));
+        assert!(due(berlin(30, 18, 0)));
+        assert!(due(berlin(31, 12, 0)));
+    }
+
+    #[test]
+    fn off() {
+        let session = session(ten());
+        assert!(!is_reminder_due(Reminder::Off, &session, berlin(31, 9, 59)));
+    }
+}
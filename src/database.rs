@@ -1,10 +1,13 @@
-use chrono::{DateTime, NaiveDate, Utc};
-use sqlx::sqlite::SqlitePool;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnection, SqlitePool};
 pub use sqlx::Error;
 use sqlx::{query, query_as, query_scalar, QueryBuilder};
-use teloxide::types::ChatId;
+use teloxide::types::{ChatId, MessageId};
 
-use crate::scraper::Session;
+use crate::changes::{self, SessionChange, SessionChanges, SessionMatch};
+use crate::reference::ReferenceFilter;
+use crate::scraper::{CachedPage, CourtData, CourtScrape, PageCache, Session};
 
 #[derive(Clone)]
 pub struct Database {
@@ -57,15 +60,55 @@ impl Database {
         Ok(Some(id))
     }
 
+    /// Moves subscriptions, settings and pending digest changes to the new id
+    /// of a chat, e.g. after a group was upgraded to a supergroup. Calling it
+    /// again after the migration does nothing.
     pub async fn migrate_chat_id(&self, old_chat: ChatId, new_chat: ChatId) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
         query!(
-            "UPDATE subscriptions SET chat_id = ? WHERE chat_id = ?",
+            "UPDATE OR IGNORE subscriptions SET chat_id = ? WHERE chat_id = ?",
             new_chat.0,
             old_chat.0
         )
-        .execute(&self.pool)
-        .await
-        .map(|_| ())
+        .execute(&mut *transaction)
+        .await?;
+        // the new chat already has subscriptions with these names
+        query!("DELETE FROM subscriptions WHERE chat_id = ?", old_chat.0)
+            .execute(&mut *transaction)
+            .await?;
+
+        query!(
+            "UPDATE pending_changes SET chat_id = ? WHERE chat_id = ?",
+            new_chat.0,
+            old_chat.0
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        // the new chat hasn't seen any message of the chains, so they are
+        // sent there from the start
+        query!(
+            "UPDATE outbox SET chat_id = ?, sent_messages = 0, reply_to = NULL WHERE chat_id = ?",
+            new_chat.0,
+            old_chat.0
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        // settings made in the new chat take precedence
+        query!(
+            "UPDATE OR IGNORE chat_settings SET chat_id = ? WHERE chat_id = ?",
+            new_chat.0,
+            old_chat.0
+        )
+        .execute(&mut *transaction)
+        .await?;
+        query!("DELETE FROM chat_settings WHERE chat_id = ?", old_chat.0)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await
     }
 
     pub async fn get_subscription_by_id(
@@ -81,6 +124,52 @@ impl Database {
         .await
     }
 
+    pub async fn get_subscription_by_name(
+        &self,
+        chat_id: ChatId,
+        name: &str,
+    ) -> Result<Option<Subscription>, Error> {
+        query_as!(
+            Subscription,
+            "SELECT * FROM subscriptions WHERE chat_id = ? AND name = ?",
+            chat_id.0,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn get_subscription_by_feed_token(
+        &self,
+        feed_token: &str,
+    ) -> Result<Option<Subscription>, Error> {
+        query_as!(
+            Subscription,
+            "SELECT * FROM subscriptions WHERE feed_token = ?",
+            feed_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Sets or, if `feed_token` is `None`, revokes the feed token.
+    pub async fn set_feed_token(
+        &self,
+        chat_id: ChatId,
+        name: &str,
+        feed_token: Option<&str>,
+    ) -> Result<bool, Error> {
+        query!(
+            "UPDATE subscriptions SET feed_token = ? WHERE chat_id = ? AND name = ?",
+            feed_token,
+            chat_id.0,
+            name
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
     pub async fn set_subscription_confirmation_sent(
         &self,
         subscription_id: i64,
@@ -105,6 +194,117 @@ impl Database {
         .map(|r| r.rows_affected() > 0)
     }
 
+    /// Stops notifications to a chat the bot can't send messages to anymore.
+    /// Returns whether any subscription was active.
+    pub async fn deactivate_chat(
+        &self,
+        chat_id: ChatId,
+        reason: InactiveReason,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let reason = reason.as_str();
+        query!(
+            "UPDATE subscriptions SET active = 0, inactive_reason = ?, inactive_since = ?
+            WHERE chat_id = ? AND active != 0",
+            reason,
+            now,
+            chat_id.0
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    /// Returns whether any subscription was inactive.
+    pub async fn reactivate_chat(&self, chat_id: ChatId) -> Result<bool, Error> {
+        query!(
+            "UPDATE subscriptions SET active = 1, inactive_reason = NULL, inactive_since = NULL
+            WHERE chat_id = ? AND active = 0",
+            chat_id.0
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    /// Deletes the subscriptions that became inactive before `before` and
+    /// returns how many.
+    pub async fn purge_inactive_subscriptions(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        query!(
+            "DELETE FROM subscriptions WHERE active = 0 AND inactive_since < ?",
+            before
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected())
+    }
+
+    pub async fn remove_subscription_by_id(&self, subscription_id: i64) -> Result<bool, Error> {
+        query!(
+            "DELETE FROM subscriptions WHERE subscription_id = ?",
+            subscription_id
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    pub async fn set_subscription_paused(
+        &self,
+        subscription_id: i64,
+        paused: bool,
+    ) -> Result<bool, Error> {
+        query!(
+            "UPDATE subscriptions SET paused = ? WHERE subscription_id = ?",
+            paused,
+            subscription_id
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    /// Returns `None` if the chat already has a subscription called
+    /// `new_name`, otherwise whether the subscription was found.
+    pub async fn rename_subscription(
+        &self,
+        subscription_id: i64,
+        new_name: &str,
+    ) -> Result<Option<bool>, Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let exists: i64 = query_scalar!(
+            "SELECT COUNT(*) FROM subscriptions
+            WHERE name = ? AND subscription_id != ? AND chat_id = (
+                SELECT chat_id FROM subscriptions WHERE subscription_id = ?
+            )",
+            new_name,
+            subscription_id,
+            subscription_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        if exists > 0 {
+            transaction.rollback().await?;
+            return Ok(None);
+        }
+
+        let found = query!(
+            "UPDATE subscriptions SET name = ? WHERE subscription_id = ?",
+            new_name,
+            subscription_id
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected()
+            > 0;
+
+        transaction.commit().await?;
+
+        Ok(Some(found))
+    }
+
     pub async fn get_subscriptions_by_chat(
         &self,
         chat_id: ChatId,
@@ -116,7 +316,13 @@ impl Database {
                 s.chat_id,
                 s.confirmation_sent,
                 s.name,
-                s.reference_filter
+                s.reference_filter,
+                s.reminder_lead,
+                s.feed_token,
+                s.active,
+                s.inactive_reason,
+                s.inactive_since,
+                s.paused
             FROM subscriptions s LEFT JOIN courts c ON s.court = c.name
             WHERE s.chat_id = ?",
         )
@@ -129,67 +335,223 @@ impl Database {
         &self,
         court: &str,
     ) -> Result<Vec<Subscription>, Error> {
-        query_as!(
-            Subscription,
-            "SELECT * FROM subscriptions WHERE court = ? AND confirmation_sent != 0",
-            court
-        )
-        .fetch_all(&self.pool)
-        .await
+        confirmed_subscriptions(&mut *self.pool.acquire().await?, court).await
     }
 
+    /// Stores the result of an update. If `scrape` is `Some`, its sessions
+    /// replace the currently listed sessions of the court while keeping their
+    /// history. Partial scrapes only add or change sessions, but never remove
+    /// any. The notifications about the changes are queued in the outbox or,
+    /// for digests, as pending changes.
     pub async fn update_court_data(
         &self,
         court: &str,
         meta: &CourtMeta,
-        sessions: Option<&[Session]>,
+        scrape: Option<&CourtScrape>,
+        today: NaiveDate,
     ) -> Result<(), Error> {
         let mut transaction = self.pool.begin().await?;
 
         query!(
-            "INSERT INTO courts (name, full_name, last_update)
-                VALUES($1, $2, $3) 
+            "INSERT INTO courts (name, full_name, last_update, last_success)
+                VALUES($1, $2, $3, $4) 
                 ON CONFLICT(name) 
-                DO UPDATE SET full_name = $2, last_update = $3",
+                DO UPDATE SET full_name = $2, last_update = $3, last_success = $4",
             court,
             meta.full_name,
-            meta.last_update
+            meta.last_update,
+            meta.last_success
         )
         .execute(&mut *transaction)
         .await?;
 
-        if let Some(sessions) = sessions {
-            // Delete old sessions for the court
-            query!("DELETE FROM sessions WHERE court = ?", court)
-                .execute(&mut *transaction)
-                .await?;
+        let Some(scrape) = scrape else {
+            return transaction.commit().await;
+        };
+        let sessions = &scrape.data.sessions[..];
 
-            // Insert new sessions
-            for session in sessions {
+        let now = meta.last_update;
+        let old: Vec<StoredSession> = query_as(
+            "SELECT session_id,date,time,start_time,end_time,type,lawsuit,hall,reference,note FROM sessions
+            WHERE court = ? AND removed_at IS NULL",
+        )
+        .bind(court)
+        .fetch_all(&mut *transaction)
+        .await?;
+        // sessions on unchanged pages are neither in the scrape nor affected by it
+        let mut old: Vec<_> = old
+            .into_iter()
+            .filter(|s| !scrape.unchanged_dates.contains(&s.session.date))
+            .collect();
+
+        // an improved parser must not lead to reported changes, e.g. of the
+        // whitespace or the times derived from the text
+        for stored in &mut old {
+            let session = &mut stored.session;
+            if session.normalize() {
                 query!(
-                    "INSERT INTO sessions (court, date, time, type, lawsuit, hall, reference, note)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-                    court,
-                    session.date,
+                    "UPDATE sessions SET time = ?, start_time = ?, end_time = ?, type = ?,
+                        lawsuit = ?, hall = ?, reference = ?, note = ?
+                    WHERE session_id = ?",
                     session.time,
+                    session.start_time,
+                    session.end_time,
                     session.r#type,
                     session.lawsuit,
                     session.hall,
                     session.reference,
-                    session.note
+                    session.note,
+                    stored.session_id
                 )
                 .execute(&mut *transaction)
                 .await?;
             }
         }
+        let old_sessions: Vec<_> = old.iter().map(|s| s.session.clone()).collect();
 
-        transaction.commit().await?;
+        let mut matches = changes::match_sessions(&old_sessions, sessions);
+        if scrape.is_partial() {
+            log::warn!("{court}: Partial update, not removing any sessions");
+            matches.retain(|m| !matches!(m, SessionMatch::Removed(_)));
+        }
+
+        for m in &matches {
+            match *m {
+                SessionMatch::Removed(o) => {
+                    query!(
+                        "UPDATE sessions SET removed_at = ? WHERE session_id = ?",
+                        now,
+                        old[o].session_id
+                    )
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+                SessionMatch::Changed(o, n) => {
+                    let (id, old) = (old[o].session_id, &old[o].session);
+                    update_session(&mut transaction, id, old, &sessions[n], now).await?;
+                }
+                SessionMatch::Added(n) => {
+                    let session = &sessions[n];
 
-        Ok(())
+                    // a session that is listed again continues its history
+                    let removed: Option<StoredSession> = query_as(
+                        "SELECT session_id,date,time,start_time,end_time,type,lawsuit,hall,reference,note
+                        FROM sessions
+                        WHERE court = ? AND date = ? AND reference = ? AND lawsuit = ?
+                            AND removed_at IS NOT NULL
+                        ORDER BY removed_at DESC LIMIT 1",
+                    )
+                    .bind(court)
+                    .bind(session.date)
+                    .bind(&session.reference)
+                    .bind(&session.lawsuit)
+                    .fetch_optional(&mut *transaction)
+                    .await?;
+                    if let Some(mut removed) = removed {
+                        let id = removed.session_id;
+                        removed.session.normalize();
+                        query!(
+                            "INSERT INTO session_changes
+                                (session_id, changed_at, field, old_value, new_value)
+                            SELECT session_id, ?, 'removed_at', removed_at, ''
+                            FROM sessions WHERE session_id = ?",
+                            now,
+                            id
+                        )
+                        .execute(&mut *transaction)
+                        .await?;
+                        query!(
+                            "UPDATE sessions SET removed_at = NULL WHERE session_id = ?",
+                            id
+                        )
+                        .execute(&mut *transaction)
+                        .await?;
+                        update_session(&mut transaction, id, &removed.session, session, now)
+                            .await?;
+                        continue;
+                    }
+
+                    query!(
+                        "INSERT INTO sessions
+                            (court, date, time, start_time, end_time, type, lawsuit, hall, reference, note, first_seen, last_seen)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                        court,
+                        session.date,
+                        session.time,
+                        session.start_time,
+                        session.end_time,
+                        session.r#type,
+                        session.lawsuit,
+                        session.hall,
+                        session.reference,
+                        session.note,
+                        now,
+                        now
+                    )
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+            }
+        }
+
+        let mut update_last_seen = QueryBuilder::new("UPDATE sessions SET last_seen = ");
+        update_last_seen
+            .push_bind(now)
+            .push(" WHERE removed_at IS NULL AND court = ")
+            .push_bind(court)
+            .push(" AND date NOT IN (");
+        let mut dates = update_last_seen.separated(", ");
+        for date in &scrape.failed_dates {
+            dates.push_bind(date);
+        }
+        update_last_seen.push(")");
+        update_last_seen.build().execute(&mut *transaction).await?;
+
+        query!("DELETE FROM page_cache WHERE court = ?", court)
+            .execute(&mut *transaction)
+            .await?;
+        for (url, page) in &scrape.pages {
+            query!(
+                "INSERT OR REPLACE INTO page_cache (url, court, etag, last_modified, content_hash)
+                VALUES (?, ?, ?, ?, ?)",
+                url,
+                court,
+                page.etag,
+                page.last_modified,
+                page.content_hash
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        let changes = SessionChanges::from_matches(&matches, &old_sessions, sessions, today);
+        if !scrape.unchanged {
+            queue_notifications(
+                &mut transaction,
+                court,
+                &scrape.data.full_name,
+                &changes,
+                now,
+            )
+            .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    pub async fn get_page_cache(&self, court: &str) -> Result<PageCache, Error> {
+        let rows: Vec<CachedPageRow> = query_as(
+            "SELECT url, etag, last_modified, content_hash FROM page_cache WHERE court = ?",
+        )
+        .bind(court)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.url, row.page)).collect())
     }
 
     pub async fn get_court_meta(&self, court_name: &str) -> Result<Option<CourtMeta>, Error> {
-        query_as("SELECT full_name, last_update FROM courts WHERE name=?")
+        query_as("SELECT full_name, last_update, last_success FROM courts WHERE name=?")
             .bind(court_name)
             .fetch_optional(&self.pool)
             .await
@@ -201,17 +563,298 @@ impl Database {
         date_filter: Option<NaiveDate>,
     ) -> Result<Vec<Session>, Error> {
         let mut query = QueryBuilder::new(
-            "SELECT date,time,type,lawsuit,hall,reference,note FROM sessions WHERE court = ",
+            "SELECT date,time,start_time,end_time,type,lawsuit,hall,reference,note FROM sessions
+            WHERE removed_at IS NULL AND court = ",
         );
         query.push_bind(court_name);
 
         if let Some(date) = date_filter {
             query.push(" AND date = ").push_bind(date.to_string());
         }
+        // sessions without a known time last
+        query.push(" ORDER BY date, start_time IS NULL, start_time, session_id");
 
         query.build_query_as().fetch_all(&self.pool).await
     }
 
+    pub async fn get_upcoming_sessions(
+        &self,
+        court_name: &str,
+        from: NaiveDate,
+        until: NaiveDate,
+    ) -> Result<Vec<StoredSession>, Error> {
+        query_as(
+            "SELECT session_id,date,time,start_time,end_time,type,lawsuit,hall,reference,note FROM sessions
+            WHERE removed_at IS NULL AND court = ? AND date >= ? AND date <= ?",
+        )
+        .bind(court_name)
+        .bind(from)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn set_subscription_reminder(
+        &self,
+        chat_id: ChatId,
+        name: &str,
+        reminder: Reminder,
+    ) -> Result<bool, Error> {
+        let reminder_lead = reminder.to_column();
+        query!(
+            "UPDATE subscriptions SET reminder_lead = ? WHERE chat_id = ? AND name = ?",
+            reminder_lead,
+            chat_id.0,
+            name
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+    }
+
+    /// Queues the reminder of a subscription for a session in the outbox,
+    /// unless that has been done before.
+    pub async fn queue_reminder(
+        &self,
+        sub: &Subscription,
+        session: &StoredSession,
+        court_name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let new = query!(
+            "INSERT OR IGNORE INTO reminders_sent (subscription_id, session_id, sent_at)
+            VALUES (?, ?, ?)",
+            sub.subscription_id,
+            session.session_id,
+            now
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected()
+            > 0;
+
+        if new {
+            let notification = Notification::Reminder {
+                subscription_name: sub.name.clone(),
+                court_name: court_name.to_string(),
+                session: session.session.clone(),
+            };
+            queue(&mut transaction, ChatId(sub.chat_id), &notification, now).await?;
+        }
+
+        transaction.commit().await
+    }
+
+    pub async fn get_chat_settings(&self, chat_id: ChatId) -> Result<ChatSettings, Error> {
+        chat_settings(&mut *self.pool.acquire().await?, chat_id).await
+    }
+
+    /// Also resets the time of the last digest, so the first digest will be
+    /// sent at the next scheduled time.
+    pub async fn set_delivery_mode(
+        &self,
+        chat_id: ChatId,
+        mode: DeliveryMode,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let (delivery, digest_time, digest_weekday) = match mode {
+            DeliveryMode::Immediate => ("immediate", NaiveTime::MIN, 0),
+            DeliveryMode::Daily { time } => ("daily", time, 0),
+            DeliveryMode::Weekly { weekday, time } => ("weekly", time, weekday),
+        };
+
+        query!(
+            "INSERT INTO chat_settings (chat_id, delivery, digest_time, digest_weekday, last_digest)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT(chat_id)
+                DO UPDATE SET delivery = $2, digest_time = $3, digest_weekday = $4, last_digest = $5",
+            chat_id.0,
+            delivery,
+            digest_time,
+            digest_weekday,
+            now
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn get_chats_with_pending_changes(&self) -> Result<Vec<ChatSettings>, Error> {
+        let chats = query_scalar!("SELECT DISTINCT chat_id FROM pending_changes")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut result = Vec::with_capacity(chats.len());
+        for chat_id in chats {
+            result.push(self.get_chat_settings(ChatId(chat_id)).await?);
+        }
+        Ok(result)
+    }
+
+    /// Moves all pending changes of a chat into a digest in the outbox and
+    /// stores the time of the digest.
+    pub async fn queue_digest(&self, chat_id: ChatId, now: DateTime<Utc>) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let rows = query!(
+            "SELECT subscription_name, court_name, change FROM pending_changes
+            WHERE chat_id = ? ORDER BY pending_id",
+            chat_id.0
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        query!("DELETE FROM pending_changes WHERE chat_id = ?", chat_id.0)
+            .execute(&mut *transaction)
+            .await?;
+
+        query!(
+            "INSERT INTO chat_settings (chat_id, last_digest) VALUES ($1, $2)
+                ON CONFLICT(chat_id) DO UPDATE SET last_digest = $2",
+            chat_id.0,
+            now
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let pending: Vec<_> = rows
+            .into_iter()
+            .filter_map(|row| match serde_json::from_str(&row.change) {
+                Ok(change) => Some(PendingChange {
+                    subscription_name: row.subscription_name,
+                    court_name: row.court_name,
+                    change,
+                }),
+                Err(e) => {
+                    log::error!("Invalid pending change in db: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        if !pending.is_empty() {
+            queue(
+                &mut transaction,
+                chat_id,
+                &Notification::Digest(pending),
+                now,
+            )
+            .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    /// The oldest outbox entry of every chat, if it is due at `now`. Later
+    /// entries wait, so the notifications of a chat stay in order.
+    pub async fn get_due_outbox_entries(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<OutboxEntry>, Error> {
+        let rows = query!(
+            "SELECT outbox_id, chat_id, subscription_name, court_name, changes, kind, attempts,
+                sent_messages, reply_to
+            FROM outbox o
+            WHERE next_attempt <= ? AND NOT EXISTS (
+                SELECT * FROM outbox e WHERE e.chat_id = o.chat_id AND e.outbox_id < o.outbox_id
+            )
+            ORDER BY outbox_id",
+            now
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| OutboxEntry {
+                outbox_id: row.outbox_id,
+                chat_id: ChatId(row.chat_id),
+                notification: Notification::from_row(
+                    &row.kind,
+                    row.subscription_name,
+                    row.court_name,
+                    &row.changes,
+                ),
+                attempts: row.attempts,
+                sent_messages: row.sent_messages as usize,
+                reply_to: row.reply_to.map(|id| MessageId(id as i32)),
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Remembers how many messages of an entry were sent, so they are not
+    /// sent again after a failure.
+    pub async fn set_outbox_progress(
+        &self,
+        outbox_id: i64,
+        sent_messages: usize,
+        reply_to: Option<MessageId>,
+    ) -> Result<(), Error> {
+        let sent_messages = sent_messages as i64;
+        let reply_to = reply_to.map(|id| id.0);
+        query!(
+            "UPDATE outbox SET sent_messages = ?, reply_to = ? WHERE outbox_id = ?",
+            sent_messages,
+            reply_to,
+            outbox_id
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn postpone_outbox_entry(
+        &self,
+        outbox_id: i64,
+        attempts: i64,
+        next_attempt: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        query!(
+            "UPDATE outbox SET attempts = ?, next_attempt = ? WHERE outbox_id = ?",
+            attempts,
+            next_attempt,
+            outbox_id
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn remove_outbox_entry(&self, outbox_id: i64) -> Result<(), Error> {
+        query!("DELETE FROM outbox WHERE outbox_id = ?", outbox_id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+    }
+
+    /// Active sessions of all courts from `from` on, ordered by court and time
+    pub async fn get_all_upcoming_sessions(
+        &self,
+        from: NaiveDate,
+    ) -> Result<Vec<CourtSession>, Error> {
+        query_as(
+            "SELECT s.court, c.full_name,
+                s.date, s.time, s.start_time, s.end_time, s.type, s.lawsuit, s.hall, s.reference, s.note
+            FROM sessions s LEFT JOIN courts c ON c.name = s.court
+            WHERE s.removed_at IS NULL AND s.date >= ?
+            ORDER BY s.court, s.date, s.start_time IS NULL, s.start_time, s.session_id",
+        )
+        .bind(from)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Pairs of court id and full name of all courts that were ever scraped
+    pub async fn get_court_names(&self) -> Result<Vec<(String, String)>, Error> {
+        query_as("SELECT name, full_name FROM courts WHERE full_name IS NOT NULL")
+            .fetch_all(&self.pool)
+            .await
+    }
+
     pub async fn get_subscribed_courts(&self) -> Result<Vec<String>, Error> {
         query_scalar!("SELECT DISTINCT court FROM subscriptions")
             .fetch_all(&self.pool)
@@ -219,6 +862,178 @@ impl Database {
     }
 }
 
+/// Adds a notification to the outbox, to be sent right away
+async fn queue(
+    conn: &mut SqliteConnection,
+    chat_id: ChatId,
+    notification: &Notification,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let (kind, json) = notification.to_row();
+    let (subscription_name, court_name) = match notification {
+        Notification::Changes {
+            subscription_name,
+            court_name,
+            ..
+        }
+        | Notification::Reminder {
+            subscription_name,
+            court_name,
+            ..
+        } => (subscription_name.as_str(), court_name.as_str()),
+        Notification::Digest(_) => ("", ""),
+    };
+
+    query!(
+        "INSERT INTO outbox
+            (chat_id, subscription_name, court_name, changes, kind, created_at, next_attempt)
+        VALUES (?, ?, ?, ?, ?, ?, ?)",
+        chat_id.0,
+        subscription_name,
+        court_name,
+        json,
+        kind,
+        now,
+        now
+    )
+    .execute(&mut *conn)
+    .await
+    .map(|_| ())
+}
+
+/// Stores the details of `new` for the session `id`, logging the fields that
+/// differ from `old`.
+async fn update_session(
+    conn: &mut SqliteConnection,
+    id: i64,
+    old: &Session,
+    new: &Session,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    for (field, old_value, new_value) in [
+        ("time", &old.time, &new.time),
+        ("type", &old.r#type, &new.r#type),
+        ("hall", &old.hall, &new.hall),
+        ("note", &old.note, &new.note),
+    ] {
+        if old_value != new_value {
+            query!(
+                "INSERT INTO session_changes
+                    (session_id, changed_at, field, old_value, new_value)
+                VALUES (?, ?, ?, ?, ?)",
+                id,
+                now,
+                field,
+                old_value,
+                new_value
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    query!(
+        "UPDATE sessions
+        SET time = ?, start_time = ?, end_time = ?, type = ?, hall = ?, note = ?
+        WHERE session_id = ?",
+        new.time,
+        new.start_time,
+        new.end_time,
+        new.r#type,
+        new.hall,
+        new.note,
+        id
+    )
+    .execute(&mut *conn)
+    .await
+    .map(|_| ())
+}
+
+async fn chat_settings(
+    conn: &mut SqliteConnection,
+    chat_id: ChatId,
+) -> Result<ChatSettings, Error> {
+    let row: Option<ChatSettingsRow> = query_as(
+        "SELECT chat_id, delivery, digest_time, digest_weekday, last_digest
+        FROM chat_settings WHERE chat_id = ?",
+    )
+    .bind(chat_id.0)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(match row {
+        Some(row) => row.into(),
+        None => ChatSettings {
+            chat_id,
+            delivery_mode: DeliveryMode::Immediate,
+            last_digest: None,
+        },
+    })
+}
+
+/// Subscriptions of active chats that were told about the current sessions,
+/// except paused ones
+async fn confirmed_subscriptions(
+    conn: &mut SqliteConnection,
+    court: &str,
+) -> Result<Vec<Subscription>, Error> {
+    query_as!(
+        Subscription,
+        "SELECT * FROM subscriptions
+        WHERE court = ? AND confirmation_sent != 0 AND active != 0 AND paused = 0",
+        court
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Queues the changes matching each subscription of `court`, either in the
+/// outbox or, for chats that get digests, as pending changes.
+async fn queue_notifications(
+    conn: &mut SqliteConnection,
+    court: &str,
+    full_name: &str,
+    changes: &SessionChanges,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    for sub in confirmed_subscriptions(conn, court).await? {
+        let changes = changes.matching(&ReferenceFilter::new(&sub.reference_filter));
+        if changes.is_empty() {
+            continue;
+        }
+
+        let chat_id = ChatId(sub.chat_id);
+        let settings = chat_settings(conn, chat_id).await?;
+
+        if settings.delivery_mode == DeliveryMode::Immediate {
+            let notification = Notification::Changes {
+                subscription_name: sub.name.clone(),
+                court_name: full_name.to_string(),
+                changes,
+            };
+            queue(conn, chat_id, &notification, now).await?;
+        } else {
+            for change in &changes {
+                let change = serde_json::to_string(change).expect("serialization cannot fail");
+                query!(
+                    "INSERT INTO pending_changes
+                        (chat_id, subscription_name, court_name, change, created_at)
+                    VALUES (?, ?, ?, ?, ?)",
+                    chat_id.0,
+                    sub.name,
+                    full_name,
+                    change,
+                    now
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, sqlx::FromRow)]
 #[allow(unused)]
 pub struct Subscription {
@@ -228,10 +1043,241 @@ pub struct Subscription {
     pub confirmation_sent: i64,
     pub name: String,
     pub reference_filter: String,
+    pub reminder_lead: Option<i64>,
+    pub feed_token: Option<String>,
+    pub active: i64,
+    pub inactive_reason: Option<String>,
+    /// RFC3339 timestamp
+    pub inactive_since: Option<String>,
+    pub paused: i64,
+}
+
+impl Subscription {
+    pub fn reminder(&self) -> Reminder {
+        Reminder::from_column(self.reminder_lead)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reminder {
+    Off,
+    EveningBefore,
+    MinutesBefore(i64),
+}
+
+impl Reminder {
+    fn from_column(reminder_lead: Option<i64>) -> Self {
+        match reminder_lead {
+            None => Reminder::EveningBefore,
+            Some(0) => Reminder::Off,
+            Some(minutes) => Reminder::MinutesBefore(minutes),
+        }
+    }
+
+    fn to_column(self) -> Option<i64> {
+        match self {
+            Reminder::Off => Some(0),
+            Reminder::EveningBefore => None,
+            Reminder::MinutesBefore(minutes) => Some(minutes),
+        }
+    }
+}
+
+/// Why the bot can't send messages to a chat anymore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InactiveReason {
+    /// The user blocked the bot
+    Blocked,
+    /// The bot was removed from the group
+    Kicked,
+    ChatNotFound,
+    /// The user's account or the group was deleted
+    Deactivated,
+    /// Any other reason Telegram refuses to deliver messages
+    Forbidden,
+}
+
+impl InactiveReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            InactiveReason::Blocked => "blocked",
+            InactiveReason::Kicked => "kicked",
+            InactiveReason::ChatNotFound => "chat_not_found",
+            InactiveReason::Deactivated => "deactivated",
+            InactiveReason::Forbidden => "forbidden",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryMode {
+    Immediate,
+    /// Daily digest at the given local time
+    Daily {
+        time: NaiveTime,
+    },
+    /// Weekly digest, weekday 0 is Monday
+    Weekly {
+        weekday: u8,
+        time: NaiveTime,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatSettings {
+    pub chat_id: ChatId,
+    pub delivery_mode: DeliveryMode,
+    pub last_digest: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ChatSettingsRow {
+    chat_id: i64,
+    delivery: String,
+    digest_time: NaiveTime,
+    digest_weekday: u8,
+    last_digest: Option<DateTime<Utc>>,
+}
+
+impl From<ChatSettingsRow> for ChatSettings {
+    fn from(row: ChatSettingsRow) -> Self {
+        let delivery_mode = match row.delivery.as_str() {
+            "daily" => DeliveryMode::Daily {
+                time: row.digest_time,
+            },
+            "weekly" => DeliveryMode::Weekly {
+                weekday: row.digest_weekday,
+                time: row.digest_time,
+            },
+            _ => DeliveryMode::Immediate,
+        };
+
+        ChatSettings {
+            chat_id: ChatId(row.chat_id),
+            delivery_mode,
+            last_digest: row.last_digest,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Notification {
+    /// Changes concerning one subscription
+    Changes {
+        subscription_name: String,
+        court_name: String,
+        changes: Vec<SessionChange>,
+    },
+    Digest(Vec<PendingChange>),
+    Reminder {
+        subscription_name: String,
+        court_name: String,
+        session: Session,
+    },
+}
+
+impl Notification {
+    /// The kind stored in the outbox and the JSON of the content
+    fn to_row(&self) -> (&'static str, String) {
+        let json = match self {
+            Notification::Changes { changes, .. } => serde_json::to_string(changes),
+            Notification::Digest(pending) => serde_json::to_string(pending),
+            Notification::Reminder { session, .. } => serde_json::to_string(session),
+        };
+        let kind = match self {
+            Notification::Changes { .. } => "changes",
+            Notification::Digest(_) => "digest",
+            Notification::Reminder { .. } => "reminder",
+        };
+        (kind, json.expect("serialization cannot fail"))
+    }
+
+    fn from_row(kind: &str, subscription_name: String, court_name: String, json: &str) -> Self {
+        let parsed = match kind {
+            "digest" => serde_json::from_str(json).map(Notification::Digest),
+            "reminder" => serde_json::from_str(json).map(|session| Notification::Reminder {
+                subscription_name,
+                court_name,
+                session,
+            }),
+            _ => serde_json::from_str(json).map(|changes| Notification::Changes {
+                subscription_name,
+                court_name,
+                changes,
+            }),
+        };
+
+        parsed.unwrap_or_else(|e| {
+            log::error!("Invalid outbox entry in db: {e}");
+            // sending nothing removes the entry
+            Notification::Digest(vec![])
+        })
+    }
+}
+
+/// A notification waiting to be sent
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub outbox_id: i64,
+    pub chat_id: ChatId,
+    pub notification: Notification,
+    pub attempts: i64,
+    /// Number of messages of the chain that were already sent
+    pub sent_messages: usize,
+    pub reply_to: Option<MessageId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingChange {
+    pub subscription_name: String,
+    pub court_name: String,
+    pub change: SessionChange,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredSession {
+    pub session_id: i64,
+    #[sqlx(flatten)]
+    pub session: Session,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct CourtSession {
+    pub court: String,
+    pub full_name: Option<String>,
+    #[sqlx(flatten)]
+    pub session: Session,
+}
+
+#[derive(sqlx::FromRow)]
+struct CachedPageRow {
+    url: String,
+    #[sqlx(flatten)]
+    page: CachedPage,
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct CourtMeta {
+    /// Name from the last successful update
     pub full_name: Option<String>,
+    /// Time of the last update attempt
     pub last_update: DateTime<Utc>,
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl CourtMeta {
+    /// Whether the last update attempt failed
+    pub fn last_update_failed(&self) -> bool {
+        self.last_success
+            .is_none_or(|last_success| last_success < self.last_update)
+    }
+}
+
+/// Court data from the database, possibly older than the last update attempt
+#[derive(Debug, Clone)]
+pub struct CachedCourtData {
+    pub data: CourtData,
+    /// If the website could not be reached on the last update, the time the
+    /// data was retrieved
+    pub stale_since: Option<DateTime<Utc>>,
 }
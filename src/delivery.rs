@@ -0,0 +1,158 @@
+//! Sends the notifications and digests queued in the outbox. An entry is only
+//! removed once all of its messages were sent, and the progress is stored
+//! after every message, so nothing is lost or sent twice if sending fails
+//! midway.
+
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use teloxide::prelude::*;
+use teloxide::types::ReplyParameters;
+use teloxide::RequestError;
+use tokio::sync::oneshot;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::database::{Database, Error as DbError, Notification, OutboxEntry};
+use crate::{inactive, messages, migrate_chat, Bot};
+
+const DELIVERY_PERIOD: Duration = Duration::from_secs(5);
+/// An entry is dropped after this many failed attempts
+const MAX_ATTEMPTS: i64 = 10;
+
+/// Doubles with every failed attempt, from one minute up to about eight hours
+fn retry_delay(attempts: i64) -> TimeDelta {
+    TimeDelta::minutes(1 << (attempts - 1).clamp(0, 9))
+}
+
+/// Sends the remaining messages of `entry` and returns whether it is done.
+async fn deliver(bot: &Bot, database: &Database, entry: OutboxEntry) -> Result<bool, DbError> {
+    let (outbox_id, chat_id) = (entry.outbox_id, entry.chat_id);
+
+    // a reminder that could not be sent in time is of no use anymore
+    if let Notification::Reminder { session, .. } = &entry.notification {
+        let today = Utc::now()
+            .with_timezone(&chrono_tz::Europe::Berlin)
+            .date_naive();
+        if session.date < today {
+            log::info!("Dropping outdated reminder {outbox_id} to chat {chat_id}");
+            database.remove_outbox_entry(outbox_id).await?;
+            return Ok(true);
+        }
+    }
+    let msgs = match &entry.notification {
+        Notification::Changes {
+            subscription_name,
+            court_name,
+            changes,
+        } => messages::sessions_updated(changes, court_name, subscription_name),
+        Notification::Digest(pending) => messages::digest(pending),
+        Notification::Reminder {
+            subscription_name,
+            court_name,
+            session,
+        } => messages::session_reminder(session, court_name, subscription_name),
+    };
+
+    let mut reply_to = entry.reply_to;
+    for (index, msg) in msgs.into_iter().enumerate().skip(entry.sent_messages) {
+        let mut request = bot.send_message(chat_id, msg.into_string());
+        if let Some(reply_to) = reply_to {
+            request = request.reply_parameters(ReplyParameters::new(reply_to));
+        }
+
+        match request.await {
+            Ok(m) => {
+                reply_to.get_or_insert(m.id);
+                database
+                    .set_outbox_progress(outbox_id, index + 1, reply_to)
+                    .await?;
+            }
+            Err(RequestError::MigrateToChatId(new_chat)) => {
+                // moves the entry as well, it is sent on the next attempt
+                migrate_chat(database, chat_id, new_chat).await;
+                return Ok(false);
+            }
+            Err(RequestError::RetryAfter(seconds)) => {
+                log::warn!("Rate limited, retrying in {}s", seconds.seconds());
+                let next_attempt = Utc::now() + seconds.duration();
+                database
+                    .postpone_outbox_entry(outbox_id, entry.attempts, next_attempt)
+                    .await?;
+                return Ok(false);
+            }
+            Err(e) => {
+                if let Some(reason) = inactive::inactive_reason(&e) {
+                    inactive::deactivate_chat(database, chat_id, reason).await;
+                    database.remove_outbox_entry(outbox_id).await?;
+                    return Ok(true);
+                }
+
+                let attempts = entry.attempts + 1;
+                if attempts >= MAX_ATTEMPTS {
+                    log::error!("Giving up notification {outbox_id} to chat {chat_id}: {e}");
+                    database.remove_outbox_entry(outbox_id).await?;
+                    return Ok(true);
+                }
+
+                log::warn!("error sending message: {e}");
+                let next_attempt = Utc::now() + retry_delay(attempts);
+                database
+                    .postpone_outbox_entry(outbox_id, attempts, next_attempt)
+                    .await?;
+                return Ok(false);
+            }
+        }
+    }
+
+    database.remove_outbox_entry(outbox_id).await?;
+    Ok(true)
+}
+
+/// Sends everything that is due. Once an entry is done, the next one of the
+/// same chat may be due, so this repeats until nothing is done anymore.
+async fn deliver_due(bot: &Bot, database: &Database, now: DateTime<Utc>) -> Result<(), DbError> {
+    loop {
+        let mut any_done = false;
+        for entry in database.get_due_outbox_entries(now).await? {
+            any_done |= deliver(bot, database, entry).await?;
+        }
+
+        if !any_done {
+            return Ok(());
+        }
+    }
+}
+
+/// Periodically sends the notifications in the outbox, until `shutdown`
+/// fires.
+pub async fn run(bot: Bot, database: Database, mut shutdown: oneshot::Receiver<()>) {
+    let mut interval = interval(DELIVERY_PERIOD);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = deliver_due(&bot, &database, Utc::now()).await {
+                    log::error!("Delivering notifications failed: {e}")
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    log::info!("Delivery task shut down.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delays() {
+        assert_eq!(retry_delay(1), TimeDelta::minutes(1));
+        assert_eq!(retry_delay(2), TimeDelta::minutes(2));
+        assert_eq!(retry_delay(4), TimeDelta::minutes(8));
+        assert_eq!(retry_delay(MAX_ATTEMPTS), TimeDelta::minutes(512));
+        assert_eq!(retry_delay(100), TimeDelta::minutes(512));
+    }
+}
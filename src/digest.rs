@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Utc};
+use chrono_tz::Europe::Berlin;
+use tokio::sync::oneshot;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::database::{ChatSettings, Database, DeliveryMode, Error as DbError};
+
+const DIGEST_PERIOD: Duration = Duration::from_secs(60);
+
+/// Returns the most recent time a digest was scheduled for, or `None` if
+/// changes are delivered immediately.
+fn last_scheduled(mode: DeliveryMode, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let now_local = now.with_timezone(&Berlin);
+
+    let (time, days_back) = match mode {
+        DeliveryMode::Immediate => return None,
+        DeliveryMode::Daily { time } => (time, u64::from(now_local.time() < time)),
+        DeliveryMode::Weekly { weekday, time } => {
+            let today = now_local.weekday().num_days_from_monday();
+            let days_back = (today + 7 - u32::from(weekday)) % 7;
+            if days_back == 0 && now_local.time() < time {
+                (time, 7)
+            } else {
+                (time, u64::from(days_back))
+            }
+        }
+    };
+
+    now_local
+        .date_naive()
+        .checked_sub_days(Days::new(days_back))?
+        .and_time(time)
+        .and_local_timezone(Berlin)
+        .earliest()
+        .map(|t| t.to_utc())
+}
+
+fn is_due(settings: &ChatSettings, now: DateTime<Utc>) -> bool {
+    match last_scheduled(settings.delivery_mode, now) {
+        // leftovers from before the chat switched to immediate delivery
+        None => true,
+        Some(scheduled) => settings.last_digest.is_none_or(|last| last < scheduled),
+    }
+}
+
+/// Queues the due digests in the outbox, from where they are sent.
+async fn queue_digests(database: &Database) -> Result<(), DbError> {
+    let now = Utc::now();
+
+    for settings in database.get_chats_with_pending_changes().await? {
+        if !is_due(&settings, now) {
+            continue;
+        }
+
+        database.queue_digest(settings.chat_id, now).await?;
+    }
+
+    Ok(())
+}
+
+/// Periodically queues the digests of all chats that don't want immediate
+/// notifications, until `shutdown` fires.
+pub async fn run(database: Database, mut shutdown: oneshot::Receiver<()>) {
+    let mut interval = interval(DIGEST_PERIOD);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = queue_digests(&database).await {
+                    log::error!("Queueing digests failed: {e}")
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    log::info!("Digest task shut down.");
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+
+    use super::*;
+
+    fn berlin(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(2024, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+            .and_local_timezone(Berlin)
+            .unwrap()
+            .to_utc()
+    }
+
+    fn eight() -> NaiveTime {
+        NaiveTime::from_hms_opt(8, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn immediate() {
+        assert_eq!(
+            last_scheduled(DeliveryMode::Immediate, berlin(3, 31, 9, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn daily() {
+        let mode = DeliveryMode::Daily { time: eight() };
+        // today, after the change to summer time
+        assert_eq!(
+            last_scheduled(mode, berlin(3, 31, 9, 0)),
+            Some(berlin(3, 31, 8, 0))
+        );
+        // today, but before the time
+        assert_eq!(
+            last_scheduled(mode, berlin(3, 31, 7, 59)),
+            Some(berlin(3, 30, 8, 0))
+        );
+    }
+
+    #[test]
+    fn weekly() {
+        // 2024-04-01 is a Monday
+        let mode = DeliveryMode::Weekly {
+            weekday: 0,
+            time: eight(),
+        };
+        assert_eq!(
+            last_scheduled(mode, berlin(4, 1, 8, 0)),
+            Some(berlin(4, 1, 8, 0))
+        );
+        // today, but before the time
+        assert_eq!(
+            last_scheduled(mode, berlin(4, 1, 7, 59)),
+            Some(berlin(3, 25, 8, 0))
+        );
+        // wraps around the start of the week
+        assert_eq!(
+            last_scheduled(mode, berlin(3, 31, 12, 0)),
+            Some(berlin(3, 25, 8, 0))
+        );
+        assert_eq!(
+            last_scheduled(mode, berlin(4, 3, 12, 0)),
+            Some(berlin(4, 1, 8, 0))
+        );
+
+        let mode = DeliveryMode::Weekly {
+            weekday: 6,
+            time: eight(),
+        };
+        assert_eq!(
+            last_scheduled(mode, berlin(4, 1, 12, 0)),
+            Some(berlin(3, 31, 8, 0))
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Built-in directory of the courts the bot knows about
+
+use lazy_static::lazy_static;
+
+/// A court as listed in `directory/courts.csv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CourtInfo {
+    /// Court id as used in commands, e.g. "vg-koeln"
+    pub id: &'static str,
+    pub full_name: &'static str,
+    /// Type of court, e.g. "Verwaltungsgericht"
+    pub kind: &'static str,
+    pub city: &'static str,
+}
+
+lazy_static! {
+    static ref COURTS: Vec<CourtInfo> = parse_courts(include_str!("directory/courts.csv"));
+}
+
+fn parse_courts(csv: &'static str) -> Vec<CourtInfo> {
+    csv.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split(';');
+            let mut next = || fields.next().expect("courts.csv: missing field");
+            CourtInfo {
+                id: next(),
+                kind: next(),
+                city: next(),
+                full_name: next(),
+            }
+        })
+        .collect()
+}
+
+/// Lowercases and transliterates umlauts, so "Köln" and "koeln" compare equal.
+/// Hyphens become spaces.
+pub fn normalize(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        match c {
+            'ä' => result.push_str("ae"),
+            'ö' => result.push_str("oe"),
+            'ü' => result.push_str("ue"),
+            'ß' => result.push_str("ss"),
+            '-' | '_' => result.push(' '),
+            c if c.is_alphanumeric() || c.is_whitespace() => result.push(c),
+            _ => (),
+        }
+    }
+    result.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+pub fn all() -> &'static [CourtInfo] {
+    &COURTS
+}
+
+pub fn get(id: &str) -> Option<&'static CourtInfo> {
+    COURTS.iter().find(|court| court.id == id)
+}
+
+/// Common abbreviations in court names
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("ag", "amtsgericht"),
+    ("lg", "landgericht"),
+    ("olg", "oberlandesgericht"),
+    ("vg", "verwaltungsgericht"),
+    ("ovg", "oberverwaltungsgericht"),
+    ("arbg", "arbeitsgericht"),
+    ("lag", "landesarbeitsgericht"),
+    ("sg", "sozialgericht"),
+    ("lsg", "landessozialgericht"),
+    ("fg", "finanzgericht"),
+    ("nrw", "nordrhein westfalen"),
+];
+
+/// Normalizes `s` and spells out abbreviations
+fn expand(s: &str) -> String {
+    normalize(s)
+        .split(' ')
+        .map(
+            |word| match ABBREVIATIONS.iter().find(|(abbr, _)| *abbr == word) {
+                Some((_, expanded)) => expanded,
+                None => word,
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether every word of `query` is the beginning of a word of `haystack`.
+/// Both must be normalized.
+fn matches_words(query: &str, haystack: &str) -> bool {
+    query.split(' ').filter(|w| !w.is_empty()).all(|word| {
+        haystack
+            .split(' ')
+            .any(|haystack_word| haystack_word.starts_with(word))
+    })
+}
+
+fn haystack(court: &CourtInfo) -> String {
+    normalize(&format!(
+        "{} {} {} {}",
+        court.id, court.full_name, court.kind, court.city
+    ))
+}
+
+/// Courts matching all words of `query` in their id, name, type or city.
+/// Abbreviations like "VG" are understood.
+pub fn search(query: &str) -> Vec<&'static CourtInfo> {
+    let query = expand(query);
+    COURTS
+        .iter()
+        .filter(|court| matches_words(&query, &haystack(court)))
+        .collect()
+}
+
+/// A court the user might have meant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub id: String,
+    pub full_name: String,
+}
+
+/// Resolves a free-text court name like "VG Köln" or "OVG NRW" to court ids.
+/// Besides the directory, `known` courts are considered, given as pairs of
+/// id and full name, e.g. from the database. An empty result means no court
+/// was found, more than one that the input is ambiguous.
+pub fn resolve(input: &str, known: &[(String, String)]) -> Vec<Candidate> {
+    let mut courts: Vec<(Candidate, String)> = COURTS
+        .iter()
+        .map(|court| {
+            let candidate = Candidate {
+                id: court.id.to_string(),
+                full_name: court.full_name.to_string(),
+            };
+            (candidate, haystack(court))
+        })
+        .collect();
+    for (id, full_name) in known {
+        let haystack = normalize(&format!("{id} {full_name}"));
+        match courts.iter_mut().find(|(c, _)| &c.id == id) {
+            // the name on the website may differ from the directory
+            Some((_, existing)) => *existing = format!("{existing} {haystack}"),
+            None => {
+                let candidate = Candidate {
+                    id: id.clone(),
+                    full_name: full_name.clone(),
+                };
+                courts.push((candidate, haystack))
+            }
+        }
+    }
+
+    let input = input.trim();
+    if let Some((candidate, _)) = courts
+        .iter()
+        .find(|(c, _)| c.id.eq_ignore_ascii_case(input))
+    {
+        return vec![candidate.clone()];
+    }
+
+    let query = expand(input);
+    if query.is_empty() {
+        return vec![];
+    }
+
+    let exact: Vec<_> = courts
+        .iter()
+        .filter(|(c, _)| query == expand(&c.full_name) || query == expand(&c.id))
+        .map(|(c, _)| c.clone())
+        .collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+
+    courts
+        .into_iter()
+        .filter(|(_, haystack)| matches_words(&query, &expand(haystack)))
+        .map(|(c, _)| c)
+        .collect()
+}
+
+/// Up to three courts whose id is close to `id`, closest first
+pub fn suggest(id: &str) -> Vec<&'static CourtInfo> {
+    let id = normalize(id);
+    let max_distance = (id.len() / 4).max(2);
+
+    let mut candidates: Vec<_> = COURTS
+        .iter()
+        .map(|court| (edit_distance(&id, &normalize(court.id)), court))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    candidates.sort_by_key(|(distance, _)| *distance);
+
+    candidates
+        .into_iter()
+        .take(3)
+        .map(|(_, court)| court)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory() {
+        let court = get("vg-koeln").unwrap();
+        assert_eq!(court.full_name, "Verwaltungsgericht Köln");
+        assert_eq!(court.city, "Köln");
+
+        let mut ids: Vec<_> = all().iter().map(|court| court.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), all().len(), "duplicate court ids");
+    }
+
+    #[test]
+    fn search_courts() {
+        let ids = |query| search(query).iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids("verwaltungsgericht köln"), ["vg-koeln"]);
+        assert_eq!(ids("Koeln VG"), ["vg-koeln"]);
+        assert!(ids("Münster").contains(&"ovg"));
+        assert!(ids("xyz").is_empty());
+        assert_eq!(ids("Verwaltungsgericht Münster"), ["vg-muenster"]);
+    }
+
+    #[test]
+    fn resolve_names() {
+        let ids = |input, known: &[(String, String)]| {
+            resolve(input, known)
+                .into_iter()
+                .map(|c| c.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("vg-koeln", &[]), ["vg-koeln"]);
+        assert_eq!(ids("VG Köln", &[]), ["vg-koeln"]);
+        assert_eq!(ids("Verwaltungsgericht Koeln", &[]), ["vg-koeln"]);
+        assert_eq!(ids("OVG NRW", &[]), ["ovg"]);
+        assert_eq!(ids("Amtsgericht Essen", &[]), ["ag-essen"]);
+        assert!(ids("Köln", &[]).len() > 1);
+        assert!(ids("Amtsgericht Atlantis", &[]).is_empty());
+
+        let known = [("ag-test".to_string(), "Amtsgericht Testhausen".to_string())];
+        assert_eq!(ids("AG Testhausen", &known), ["ag-test"]);
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(suggest("vg-koln")[0].id, "vg-koeln");
+        assert_eq!(suggest("vg-kölner")[0].id, "vg-koeln");
+        assert_eq!(suggest("lg-dusseldorf")[0].id, "lg-duesseldorf");
+        assert!(suggest("something else").is_empty());
+    }
+}
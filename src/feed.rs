@@ -0,0 +1,135 @@
+//! Optional HTTP server providing an iCalendar feed for each subscription.
+
+use std::net::SocketAddr;
+
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use rand::distributions::{Alphanumeric, DistString};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+use crate::database::{Database, Error as DbError};
+use crate::ical;
+use crate::reference::ReferenceFilter;
+
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
+    /// Address the HTTP server listens on, the server is disabled if `None`
+    pub listen_addr: Option<SocketAddr>,
+    /// Public URL under which the server is reachable
+    pub base_url: String,
+}
+
+impl FeedConfig {
+    /// Reads `FEED_LISTEN_ADDR` and `FEED_BASE_URL` from the environment. The
+    /// server is only enabled if both are set.
+    pub fn from_env() -> Self {
+        let listen_addr =
+            std::env::var("FEED_LISTEN_ADDR")
+                .ok()
+                .and_then(|addr| match addr.parse() {
+                    Ok(addr) => Some(addr),
+                    Err(e) => {
+                        log::error!("Invalid FEED_LISTEN_ADDR {addr}: {e}");
+                        None
+                    }
+                });
+
+        // the listen address is usually not reachable by users, e.g. 0.0.0.0
+        let base_url = std::env::var("FEED_BASE_URL").unwrap_or_default();
+        let listen_addr = listen_addr.filter(|_| {
+            if base_url.is_empty() {
+                log::error!("FEED_BASE_URL is required with FEED_LISTEN_ADDR, feeds are disabled");
+            }
+            !base_url.is_empty()
+        });
+
+        Self {
+            listen_addr,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.listen_addr.is_some()
+    }
+
+    pub fn feed_url(&self, token: &str) -> String {
+        format!("{}/feed/{token}.ics", self.base_url)
+    }
+}
+
+pub fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), TOKEN_LENGTH)
+}
+
+async fn build_feed(database: &Database, token: &str) -> Result<Option<String>, DbError> {
+    let Some(sub) = database.get_subscription_by_feed_token(token).await? else {
+        return Ok(None);
+    };
+
+    let full_name = database
+        .get_court_meta(&sub.court)
+        .await?
+        .and_then(|meta| meta.full_name)
+        .unwrap_or_else(|| sub.court.clone());
+    let sessions = database.get_sessions(&sub.court, None).await?;
+
+    let filter = ReferenceFilter::new(&sub.reference_filter);
+    let sessions = sessions.iter().filter(|s| filter.matches(&s.reference));
+
+    Ok(Some(ical::calendar(
+        &sub.court, &full_name, &sub.name, sessions,
+    )))
+}
+
+async fn get_feed(State(database): State<Database>, Path(file): Path<String>) -> Response {
+    let Some(token) = file.strip_suffix(".ics") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match build_feed(&database, token).await {
+        Ok(Some(calendar)) => (
+            [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+            calendar,
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            log::error!("Database error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Serves the feeds until `shutdown` fires.
+pub async fn serve(addr: SocketAddr, database: Database, shutdown: oneshot::Receiver<()>) {
+    let app = Router::new()
+        .route("/feed/:file", get(get_feed))
+        .with_state(database);
+
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Cannot listen on {addr}: {e}");
+            return;
+        }
+    };
+
+    log::info!("Serving feeds on {addr}");
+
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = shutdown.await;
+        })
+        .await;
+
+    if let Err(e) = result {
+        log::error!("Feed server failed: {e}")
+    }
+}
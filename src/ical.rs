@@ -0,0 +1,253 @@
+//! Minimal RFC 5545 calendar export of sessions.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeDelta, Utc};
+use chrono_tz::Europe::Berlin;
+
+use crate::scraper::Session;
+
+/// Assumed duration of a session if only its start time is known
+const DEFAULT_DURATION: TimeDelta = TimeDelta::hours(1);
+
+/// Escapes a TEXT value according to RFC 5545, section 3.3.11.
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                result.push('\\');
+                result.push(c)
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => (),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Writes a content line, folded after 75 octets (RFC 5545, section 3.1).
+fn write_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn format_utc(t: DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_date(d: NaiveDate) -> String {
+    d.format("%Y%m%d").to_string()
+}
+
+/// FNV-1a, used because the UIDs must not change between releases.
+fn stable_hash(s: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in s.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// `occurrence` counts the sessions of the same case on the same day, only
+/// the later ones get a suffix, so the UIDs of most sessions stay unchanged.
+fn uid(court: &str, session: &Session, occurrence: usize) -> String {
+    let key = session.key();
+    let hash = stable_hash(&format!("{court}\n{}\n{}", key.reference, key.lawsuit));
+    let suffix = match occurrence {
+        0 => String::new(),
+        n => format!("-{}", n + 1),
+    };
+    format!(
+        "{}-{hash:016x}{suffix}-{court}@sitzungsterminbot",
+        format_date(key.date)
+    )
+}
+
+fn local_to_utc(date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    date.and_time(time)
+        .and_local_timezone(Berlin)
+        .earliest()
+        .map(|t| t.to_utc())
+}
+
+fn write_event(
+    out: &mut String,
+    court: &str,
+    full_court_name: &str,
+    session: &Session,
+    occurrence: usize,
+    now: DateTime<Utc>,
+) {
+    write_line(out, "BEGIN:VEVENT");
+    write_line(out, &format!("UID:{}", uid(court, session, occurrence)));
+    write_line(out, &format!("DTSTAMP:{}", format_utc(now)));
+
+    match session
+        .start_time
+        .and_then(|time| local_to_utc(session.date, time))
+    {
+        Some(start) => {
+            let end = session
+                .end_time
+                .and_then(|time| local_to_utc(session.date, time))
+                .unwrap_or(start + DEFAULT_DURATION);
+            write_line(out, &format!("DTSTART:{}", format_utc(start)));
+            write_line(out, &format!("DTEND:{}", format_utc(end)));
+        }
+        None => {
+            let end = session.date + Days::new(1);
+            write_line(
+                out,
+                &format!("DTSTART;VALUE=DATE:{}", format_date(session.date)),
+            );
+            write_line(out, &format!("DTEND;VALUE=DATE:{}", format_date(end)));
+        }
+    }
+
+    let summary = if session.r#type.is_empty() {
+        session.reference.clone()
+    } else {
+        format!("{} {}", session.r#type, session.reference)
+    };
+    write_line(out, &format!("SUMMARY:{}", escape(&summary)));
+
+    let location = if session.hall.is_empty() {
+        full_court_name.to_string()
+    } else {
+        format!("{full_court_name}, Sitzungssaal {}", session.hall)
+    };
+    write_line(out, &format!("LOCATION:{}", escape(&location)));
+
+    let mut description = format!("Aktenzeichen: {}", session.reference);
+    if !session.lawsuit.is_empty() {
+        let _ = write!(description, "\nVerfahren: {}", session.lawsuit);
+    }
+    if !session.time.is_empty() {
+        let _ = write!(description, "\nUhrzeit laut Aushang: {}", session.time);
+    }
+    if !session.note.is_empty() {
+        let _ = write!(description, "\nHinweis: {}", session.note);
+    }
+    write_line(out, &format!("DESCRIPTION:{}", escape(&description)));
+
+    write_line(out, "END:VEVENT");
+}
+
+/// Builds an iCalendar file containing one event per session.
+pub fn calendar<'a>(
+    court: &str,
+    full_court_name: &str,
+    title: &str,
+    sessions: impl IntoIterator<Item = &'a Session>,
+) -> String {
+    let now = Utc::now();
+    let mut out = String::new();
+
+    write_line(&mut out, "BEGIN:VCALENDAR");
+    write_line(&mut out, "VERSION:2.0");
+    write_line(&mut out, "PRODID:-//SitzungsterminBot//DE");
+    write_line(&mut out, "CALSCALE:GREGORIAN");
+    write_line(&mut out, "METHOD:PUBLISH");
+    write_line(&mut out, &format!("X-WR-CALNAME:{}", escape(title)));
+
+    let mut occurrences = HashMap::new();
+    for session in sessions {
+        let occurrence = occurrences.entry(session.key()).or_insert(0);
+        write_event(&mut out, court, full_court_name, session, *occurrence, now);
+        *occurrence += 1;
+    }
+
+    write_line(&mut out, "END:VCALENDAR");
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(time: Option<(u32, u32)>) -> Session {
+        Session {
+            date: NaiveDate::from_ymd_opt(2024, 9, 2).unwrap(),
+            time: String::new(),
+            start_time: time.and_then(|(h, m)| NaiveTime::from_hms_opt(h, m, 0)),
+            end_time: None,
+            r#type: "Verhandlung".to_string(),
+            lawsuit: "A ./. B".to_string(),
+            hall: "A 101".to_string(),
+            reference: "1 K 1/24".to_string(),
+            note: String::new(),
+        }
+    }
+
+    fn lines(calendar: &str) -> Vec<&str> {
+        calendar.split("\r\n").collect()
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a\\b;c,d\r\ne"), "a\\\\b\\;c\\,d\\ne");
+        assert_eq!(escape("Köln"), "Köln");
+    }
+
+    #[test]
+    fn folding() {
+        let mut out = String::new();
+        write_line(&mut out, &"ä".repeat(50));
+
+        let lines: Vec<_> = out.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+        // 37 two octet characters fit into the first 75 octets, the
+        // continuation lines start with a space
+        assert_eq!(lines[0], "ä".repeat(37));
+        assert_eq!(lines[1], format!(" {}", "ä".repeat(13)));
+        assert!(lines.iter().all(|line| line.len() <= 75));
+
+        let mut out = String::new();
+        write_line(&mut out, &"a".repeat(75));
+        assert_eq!(out, format!("{}\r\n", "a".repeat(75)));
+    }
+
+    #[test]
+    fn timed_event() {
+        let calendar = calendar("vg-koeln", "VG Köln", "Test", [&session(Some((9, 30)))]);
+        let lines = lines(&calendar);
+        // 09:30 in summer time
+        assert!(lines.contains(&"DTSTART:20240902T073000Z"));
+        assert!(lines.contains(&"DTEND:20240902T083000Z"));
+    }
+
+    #[test]
+    fn all_day_event() {
+        let calendar = calendar("vg-koeln", "VG Köln", "Test", [&session(None)]);
+        let lines = lines(&calendar);
+        assert!(lines.contains(&"DTSTART;VALUE=DATE:20240902"));
+        assert!(lines.contains(&"DTEND;VALUE=DATE:20240903"));
+    }
+
+    #[test]
+    fn unique_uids() {
+        let sessions = [session(Some((9, 30))), session(Some((14, 0)))];
+        let calendar = calendar("vg-koeln", "VG Köln", "Test", &sessions);
+        let uids: Vec<_> = lines(&calendar)
+            .into_iter()
+            .filter(|line| line.starts_with("UID:"))
+            .collect();
+
+        assert_eq!(uids.len(), 2);
+        assert_ne!(uids[0], uids[1]);
+        // the first one keeps the UID it had without the second one
+        assert_eq!(uids[0], format!("UID:{}", uid("vg-koeln", &sessions[0], 0)));
+    }
+}
@@ -0,0 +1,115 @@
+//! Chats the bot can't send messages to anymore. Their subscriptions are
+//! paused until the chat talks to the bot again, and deleted if it doesn't
+//! within `RETENTION`.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use teloxide::types::{ChatId, ChatMemberUpdated};
+use teloxide::{ApiError, RequestError};
+use tokio::sync::oneshot;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::database::{Database, InactiveReason};
+
+const RETENTION: chrono::Duration = chrono::Duration::days(90);
+const PURGE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Whether `error` means that messages to the chat won't be delivered until
+/// the user does something about it.
+pub fn inactive_reason(error: &RequestError) -> Option<InactiveReason> {
+    let RequestError::Api(error) = error else {
+        return None;
+    };
+
+    match error {
+        ApiError::BotBlocked | ApiError::CantInitiateConversation => Some(InactiveReason::Blocked),
+        ApiError::BotKicked | ApiError::BotKickedFromSupergroup => Some(InactiveReason::Kicked),
+        ApiError::ChatNotFound => Some(InactiveReason::ChatNotFound),
+        ApiError::UserDeactivated | ApiError::GroupDeactivated => Some(InactiveReason::Deactivated),
+        // e.g. "bot was kicked from the group chat" has no variant
+        ApiError::Unknown(text) if text.starts_with("Forbidden:") => {
+            Some(InactiveReason::Forbidden)
+        }
+        _ => None,
+    }
+}
+
+pub async fn deactivate_chat(database: &Database, chat_id: ChatId, reason: InactiveReason) {
+    match database.deactivate_chat(chat_id, reason, Utc::now()).await {
+        Ok(true) => log::info!("Deactivated subscriptions of chat {chat_id}: {reason:?}"),
+        Ok(false) => (),
+        Err(e) => log::error!("Database error: {e}"),
+    }
+}
+
+pub async fn reactivate_chat(database: &Database, chat_id: ChatId) {
+    match database.reactivate_chat(chat_id).await {
+        Ok(true) => log::info!("Reactivated subscriptions of chat {chat_id}"),
+        Ok(false) => (),
+        Err(e) => log::error!("Database error: {e}"),
+    }
+}
+
+/// Follows the bot being blocked, removed from or added to a chat.
+pub async fn member_updated(database: &Database, update: &ChatMemberUpdated) {
+    let chat_id = update.chat.id;
+    if update.new_chat_member.is_present() {
+        reactivate_chat(database, chat_id).await
+    } else if update.chat.is_private() {
+        deactivate_chat(database, chat_id, InactiveReason::Blocked).await
+    } else {
+        deactivate_chat(database, chat_id, InactiveReason::Kicked).await
+    }
+}
+
+/// Periodically deletes subscriptions that were inactive for longer than
+/// `RETENTION`, until `shutdown` fires.
+pub async fn run(database: Database, mut shutdown: oneshot::Receiver<()>) {
+    let mut interval = interval(PURGE_PERIOD);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                match database.purge_inactive_subscriptions(Utc::now() - RETENTION).await {
+                    Ok(0) => (),
+                    Ok(n) => log::info!("Deleted {n} inactive subscriptions"),
+                    Err(e) => log::error!("Database error: {e}"),
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    log::info!("Inactive subscription task shut down.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        let api = |e| RequestError::Api(e);
+        assert_eq!(
+            inactive_reason(&api(ApiError::BotBlocked)),
+            Some(InactiveReason::Blocked)
+        );
+        assert_eq!(
+            inactive_reason(&api(ApiError::ChatNotFound)),
+            Some(InactiveReason::ChatNotFound)
+        );
+        assert_eq!(
+            inactive_reason(&api(ApiError::Unknown(
+                "Forbidden: bot was kicked from the group chat".into()
+            ))),
+            Some(InactiveReason::Forbidden)
+        );
+        assert_eq!(inactive_reason(&api(ApiError::MessageIsTooLong)), None);
+        assert_eq!(
+            inactive_reason(&RequestError::MigrateToChatId(ChatId(1))),
+            None
+        );
+    }
+}
@@ -1,27 +1,50 @@
+mod changes;
+mod court_choice;
 mod courts;
 mod database;
+mod delivery;
+mod digest;
+mod directory;
+mod feed;
+mod ical;
+mod inactive;
 mod messages;
+mod reference;
 mod scraper;
+mod search;
+mod subscribe_dialogue;
+mod subscription_actions;
 
 use std::sync::Arc;
 
-use courts::Courts;
+use chrono::NaiveTime;
+use courts::{CalendarReply, Courts, MAX_REMINDER_LEAD_DAYS};
 use dptree::deps;
 use teloxide::adaptors::{DefaultParseMode, Throttle};
 use teloxide::macros::BotCommands;
 use teloxide::prelude::*;
-use teloxide::types::{ParseMode, ReplyParameters};
+use teloxide::types::{
+    ChatMemberUpdated, ForceReply, InputFile, ParseMode, ReplyParameters, UpdateKind,
+};
 use teloxide::utils::command::ParseError;
+use teloxide::RequestError;
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex};
 
-use crate::database::Database;
+use crate::court_choice::CourtChoices;
+use crate::database::{CourtMeta, Database, DeliveryMode, Reminder};
+use crate::feed::FeedConfig;
 use crate::messages::{help, MarkdownString};
+use crate::subscription_actions::{Action, PendingRenames};
 
 #[derive(Error, Debug)]
 #[error("Error while parsing arguments in posix-shell manner")]
 struct ShlexError;
 
+fn split_any(s: String) -> Result<(Vec<String>,), ParseError> {
+    let split = shlex::split(&s).ok_or(ParseError::IncorrectFormat(Box::new(ShlexError)))?;
+    Ok((split,))
+}
 fn split1(s: String) -> Result<(String,), ParseError> {
     let split = shlex::split(&s).ok_or(ParseError::IncorrectFormat(Box::new(ShlexError)))?;
 
@@ -42,6 +65,26 @@ fn split1(s: String) -> Result<(String,), ParseError> {
         }),
     }
 }
+fn split2(s: String) -> Result<(String, String), ParseError> {
+    let split = shlex::split(&s).ok_or(ParseError::IncorrectFormat(Box::new(ShlexError)))?;
+
+    match split.len() {
+        ..=1 => Err(ParseError::TooFewArguments {
+            expected: 2,
+            found: split.len(),
+            message: String::from("Please use quotes like in posix-shells"),
+        }),
+        2 => {
+            let [a, b] = split.try_into().unwrap();
+            Ok((a, b))
+        }
+        3.. => Err(ParseError::TooManyArguments {
+            expected: 2,
+            found: split.len(),
+            message: String::from("Please use quotes like in posix-shells"),
+        }),
+    }
+}
 fn split3(s: String) -> Result<(String, String, String), ParseError> {
     let split = shlex::split(&s).ok_or(ParseError::IncorrectFormat(Box::new(ShlexError)))?;
 
@@ -72,12 +115,12 @@ fn split3(s: String) -> Result<(String, String, String), ParseError> {
 enum Command {
     #[command(description = "zeige diesen Text an.")]
     Help,
-    #[command(description = "abonniere ein Verfahren.", parse_with = split3)]
+    #[command(description = "abonniere ein Verfahren.", parse_with = split_any)]
     Subscribe {
-        name: String,
-        court: String,
-        reference: String,
+        args: Vec<String>,
     },
+    #[command(description = "brich das Anlegen eines Abos ab.")]
+    Cancel,
     #[command(description = "zeige deine Abos an.")]
     ListSubscriptions,
     #[command(description = "entferne ein Abo.", parse_with= split1)]
@@ -90,51 +133,258 @@ enum Command {
         date: String,
         reference: String,
     },
+    #[command(description = "exportiere Termine als Kalenderdatei.", parse_with = split_any)]
+    Ical {
+        args: Vec<String>,
+    },
+    #[command(description = "erstelle einen Kalender-Feed für ein Abo.", parse_with = split1)]
+    Feed {
+        name: String,
+    },
+    #[command(description = "widerrufe den Kalender-Feed eines Abos.", parse_with = split1)]
+    RevokeFeed {
+        name: String,
+    },
+    #[command(description = "lege fest, wann du an Termine erinnert wirst.", parse_with = split2)]
+    SetReminder {
+        name: String,
+        lead: String,
+    },
+    #[command(description = "lege fest, wann du benachrichtigt wirst.", parse_with = split_any)]
+    Digest {
+        args: Vec<String>,
+    },
+    #[command(description = "suche nach Gerichten.", parse_with = split_any)]
+    Courts {
+        args: Vec<String>,
+    },
+    #[command(description = "suche Termine bei allen Gerichten.", parse_with = split_any)]
+    Search {
+        args: Vec<String>,
+    },
     ForceUpdate {
         court: String,
     },
 }
 
+/// Parses e.g. "sofort", "täglich 8:00" or "wöchentlich Montag 8:00".
+fn parse_delivery_mode(args: &[String]) -> Option<DeliveryMode> {
+    const WEEKDAYS: [&str; 7] = [
+        "montag",
+        "dienstag",
+        "mittwoch",
+        "donnerstag",
+        "freitag",
+        "samstag",
+        "sonntag",
+    ];
+    let default_time = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+
+    let (mode, rest) = args.split_first()?;
+    let mut time = None;
+    let mut weekday = None;
+    for arg in rest {
+        let arg = arg.to_lowercase();
+        let arg = arg.as_str();
+        if arg == "uhr" {
+            // as in "8:00 Uhr"
+            continue;
+        } else if let Some(day) = WEEKDAYS
+            .iter()
+            .position(|day| arg.len() >= 2 && day.starts_with(arg))
+        {
+            weekday = Some(day as u8);
+        } else if let Ok(t) = NaiveTime::parse_from_str(arg, "%H:%M") {
+            time = Some(t);
+        } else if let Ok(hour) = arg.parse() {
+            time = Some(NaiveTime::from_hms_opt(hour, 0, 0)?);
+        } else {
+            return None;
+        }
+    }
+    let time = time.unwrap_or(default_time);
+
+    match mode.to_lowercase().as_str() {
+        "sofort" if rest.is_empty() => Some(DeliveryMode::Immediate),
+        "täglich" | "taeglich" if weekday.is_none() => Some(DeliveryMode::Daily { time }),
+        "wöchentlich" | "woechentlich" => Some(DeliveryMode::Weekly {
+            weekday: weekday.unwrap_or(0),
+            time,
+        }),
+        _ => None,
+    }
+}
+
+/// Parses "aus", "abends" or a lead time like "2h", "90min" or "1d".
+fn parse_reminder(s: &str) -> Option<Reminder> {
+    let s = s.trim().to_lowercase();
+    let minutes: i64 = match s.as_str() {
+        "aus" => return Some(Reminder::Off),
+        "abends" => return Some(Reminder::EveningBefore),
+        _ if s.ends_with("min") => s.strip_suffix("min")?.trim().parse().ok()?,
+        _ if s.ends_with('h') => s.strip_suffix('h')?.trim().parse::<i64>().ok()? * 60,
+        _ if s.ends_with('d') => s.strip_suffix('d')?.trim().parse::<i64>().ok()? * 24 * 60,
+        _ => return None,
+    };
+
+    let max_minutes = MAX_REMINDER_LEAD_DAYS as i64 * 24 * 60;
+    (1..=max_minutes)
+        .contains(&minutes)
+        .then_some(Reminder::MinutesBefore(minutes))
+}
+
 type Bot = DefaultParseMode<Throttle<teloxide::Bot>>;
 
-async fn send_chain(bot: &Bot, chat_id: ChatId, messages: Vec<MarkdownString>) {
+/// Moves everything stored for `old_chat` to `new_chat`, after a group was
+/// upgraded to a supergroup.
+async fn migrate_chat(database: &Database, old_chat: ChatId, new_chat: ChatId) {
+    log::info!("Chat {old_chat} migrated to {new_chat}");
+    if let Err(e) = database.migrate_chat_id(old_chat, new_chat).await {
+        log::error!("Database error: {e}");
+    }
+}
+
+/// Sends `messages`, all following ones as replies to the first. If the chat
+/// turns out to be migrated, the migration is done and sending continues in
+/// the new chat.
+async fn send_chain(
+    bot: &Bot,
+    database: &Database,
+    mut chat_id: ChatId,
+    messages: Vec<MarkdownString>,
+) {
     let mut reply_to = None;
     for msg in messages {
-        let mut request = bot.send_message(chat_id, msg.into_string());
-        if let Some(reply_to) = reply_to {
-            request = request.reply_parameters(ReplyParameters::new(reply_to));
-        }
-        match request.await {
-            Ok(m) => {
-                if reply_to.is_none() {
-                    reply_to = Some(m.id)
+        let text = msg.into_string();
+        loop {
+            let mut request = bot.send_message(chat_id, text.clone());
+            if let Some(reply_to) = reply_to {
+                request = request.reply_parameters(ReplyParameters::new(reply_to));
+            }
+            match request.await {
+                Ok(m) => {
+                    if reply_to.is_none() {
+                        reply_to = Some(m.id)
+                    }
+                }
+                Err(RequestError::MigrateToChatId(new_chat)) if new_chat != chat_id => {
+                    migrate_chat(database, chat_id, new_chat).await;
+                    chat_id = new_chat;
+                    // the message to reply to is in the old chat
+                    reply_to = None;
+                    continue;
+                }
+                Err(e) => {
+                    if let Some(reason) = inactive::inactive_reason(&e) {
+                        inactive::deactivate_chat(database, chat_id, reason).await;
+                        return;
+                    }
+                    log::warn!("error sending message: {e}")
                 }
             }
-            Err(e) => {
-                log::warn!("error sending message: {e}")
+            break;
+        }
+    }
+}
+
+async fn send_calendar(
+    bot: &Bot,
+    database: &Database,
+    mut chat_id: ChatId,
+    file_name: &str,
+    reply: CalendarReply,
+) {
+    match reply {
+        Ok(calendar) => {
+            let file = InputFile::memory(calendar.into_bytes()).file_name(file_name.to_owned());
+            let result = match bot.send_document(chat_id, file.clone()).await {
+                Err(RequestError::MigrateToChatId(new_chat)) => {
+                    migrate_chat(database, chat_id, new_chat).await;
+                    chat_id = new_chat;
+                    bot.send_document(chat_id, file).await
+                }
+                result => result,
+            };
+            match result {
+                Ok(_) => (),
+                Err(e) => match inactive::inactive_reason(&e) {
+                    Some(reason) => inactive::deactivate_chat(database, chat_id, reason).await,
+                    None => log::warn!("error sending calendar: {e}"),
+                },
             }
         }
+        Err(msgs) => send_chain(bot, database, chat_id, msgs).await,
     }
 }
 
+/// The old and new id of a chat that was upgraded to a supergroup. Telegram
+/// sends a service message to both chats.
+fn chat_migration(msg: Message) -> Option<(ChatId, ChatId)> {
+    if let Some(new_chat) = msg.migrate_to_chat_id() {
+        Some((msg.chat.id, *new_chat))
+    } else {
+        msg.migrate_from_chat_id()
+            .map(|old_chat| (*old_chat, msg.chat.id))
+    }
+}
+
+async fn handle_chat_migration(
+    (old_chat, new_chat): (ChatId, ChatId),
+    database: Database,
+) -> ResponseResult<()> {
+    migrate_chat(&database, old_chat, new_chat).await;
+    Ok(())
+}
+
+/// Turns a name into something usable as a file name.
+fn calendar_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{name}.ics")
+}
+
+/// The court name given in a command, if any
+fn command_court(cmd: &mut Command) -> Option<&mut String> {
+    match cmd {
+        Command::GetSessions { court, .. } | Command::ForceUpdate { court } => Some(court),
+        Command::Subscribe { args } if args.len() == 3 => Some(&mut args[1]),
+        Command::Ical { args } if args.len() == 2 => Some(&mut args[0]),
+        _ => None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn answer(
     bot: Bot,
     msg: Message,
-    cmd: Command,
+    mut cmd: Command,
     courts: Arc<Mutex<Courts>>,
     database: Database,
+    feed_config: FeedConfig,
+    court_choices: CourtChoices,
+    dialogue_storage: Arc<subscribe_dialogue::Storage>,
 ) -> ResponseResult<()> {
     log::info!("{:?}", cmd);
 
     let reply_fn = || {
         let bot = bot.clone();
         let msg = msg.clone();
+        let database = database.clone();
         move |reply: Vec<MarkdownString>| {
             let bot = bot.clone();
             let msg = msg.clone();
+            let database = database.clone();
             async move {
                 let chat_id = msg.chat.id;
-                send_chain(&bot, chat_id, reply).await;
+                send_chain(&bot, &database, chat_id, reply).await;
             }
         }
     };
@@ -158,15 +408,54 @@ async fn answer(
             }
         };
     }
+    // resolve names like "VG Köln" to the court id
+    let directory_only = matches!(cmd, Command::Subscribe { .. });
+    if let Some(court) = command_court(&mut cmd) {
+        let known = match database.get_court_names().await {
+            Ok(known) => known,
+            Err(e) => {
+                log::error!("Database error: {e}");
+                reply_and_return!(messages::internal_error())
+            }
+        };
+
+        let mut candidates = directory::resolve(court, &known);
+        // subscriptions are limited to the courts of the directory
+        if directory_only {
+            candidates.retain(|candidate| directory::get(&candidate.id).is_some());
+        }
+        if let [candidate] = &candidates[..] {
+            *court = candidate.id.clone();
+        } else if candidates.len() > court_choice::MAX_CHOICES {
+            reply_and_return!(messages::court_too_ambiguous(court))
+        } else if candidates.len() > 1 {
+            let text = messages::choose_court(court);
+            let keyboard = court_choices
+                .add(msg.clone(), cmd.clone(), candidates)
+                .await;
+            bot.send_message(msg.chat.id, text.to_string())
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .reply_markup(keyboard)
+                .await?;
+            return Ok(());
+        }
+    }
+
     match cmd {
         Command::Help => {
             reply_and_return!(help())
         }
-        Command::Subscribe {
-            name,
-            court,
-            reference,
-        } => {
+        Command::Subscribe { args } => {
+            let (name, court, reference) = match <[String; 3]>::try_from(args) {
+                Ok([name, court, reference]) => (name, court, reference),
+                Err(args) if args.is_empty() => {
+                    return subscribe_dialogue::start(&bot, &msg, dialogue_storage).await
+                }
+                Err(_) => reply_and_return!(messages::subscribe_usage()),
+            };
+            if directory::get(&court).is_none() {
+                reply_and_return!(messages::unknown_court(&court, &directory::suggest(&court)))
+            }
             get_court!(court); // assert name is valid
             let sub_id = database
                 .add_subscription(msg.chat.id, &court, &name, &reference)
@@ -186,17 +475,28 @@ async fn answer(
 
             reply_and_return!(reply)
         }
+        Command::Cancel => return subscribe_dialogue::cancel(&bot, &msg, dialogue_storage).await,
         Command::ListSubscriptions => {
-            match database.get_subscriptions_by_chat(msg.chat.id).await {
-                Ok(subs) => {
-                    let msgs = messages::list_subscriptions(&subs);
-                    reply_fn()(msgs).await;
-                }
+            let subs = match database.get_subscriptions_by_chat(msg.chat.id).await {
+                Ok(subs) => subs,
                 Err(e) => {
                     log::error!("Database error: {e}");
                     reply_and_return!(messages::internal_error());
                 }
             };
+
+            let header = messages::list_subscriptions(&subs);
+            let header = bot
+                .send_message(msg.chat.id, header.to_string())
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .await?;
+            // one message per subscription, so the buttons can edit it
+            for sub in &subs {
+                bot.send_message(msg.chat.id, messages::subscription_entry(sub).to_string())
+                    .reply_parameters(ReplyParameters::new(header.id))
+                    .reply_markup(subscription_actions::keyboard(sub))
+                    .await?;
+            }
         }
         Command::Unsubscribe { name } => {
             let reply = match database.remove_subscription(msg.chat.id, &name).await {
@@ -216,12 +516,386 @@ async fn answer(
         } => {
             get_court!(court).get_sessions(date, reference, reply_fn());
         }
-        Command::ForceUpdate { court } => get_court!(court).update(true),
+        Command::Ical { args } => {
+            let (court, reference, file_name) = match &args[..] {
+                [name] => match database.get_subscription_by_name(msg.chat.id, name).await {
+                    Ok(Some(sub)) => (sub.court, sub.reference_filter, calendar_file_name(name)),
+                    Ok(None) => reply_and_return!(messages::subscription_not_found()),
+                    Err(e) => {
+                        log::error!("Database error: {e}");
+                        reply_and_return!(messages::internal_error())
+                    }
+                },
+                [court, reference] => {
+                    let file_name = calendar_file_name(&format!("{court}-{reference}"));
+                    (court.clone(), reference.clone(), file_name)
+                }
+                _ => reply_and_return!(messages::ical_usage()),
+            };
+
+            let bot = bot.clone();
+            let database = database.clone();
+            let chat_id = msg.chat.id;
+            get_court!(court).get_calendar(reference, move |reply| async move {
+                send_calendar(&bot, &database, chat_id, &file_name, reply).await
+            });
+        }
+        Command::Feed { name } => {
+            if !feed_config.is_enabled() {
+                reply_and_return!(messages::feeds_disabled())
+            }
+
+            let sub = match database.get_subscription_by_name(msg.chat.id, &name).await {
+                Ok(Some(sub)) => sub,
+                Ok(None) => reply_and_return!(messages::subscription_not_found()),
+                Err(e) => {
+                    log::error!("Database error: {e}");
+                    reply_and_return!(messages::internal_error())
+                }
+            };
+
+            let token = match sub.feed_token {
+                Some(token) => token,
+                None => {
+                    let token = feed::generate_token();
+                    if let Err(e) = database
+                        .set_feed_token(msg.chat.id, &name, Some(&token))
+                        .await
+                    {
+                        log::error!("Database error: {e}");
+                        reply_and_return!(messages::internal_error())
+                    }
+                    token
+                }
+            };
+
+            reply_and_return!(messages::feed_url(&name, &feed_config.feed_url(&token)))
+        }
+        Command::RevokeFeed { name } => {
+            let reply = match database.set_feed_token(msg.chat.id, &name, None).await {
+                Ok(found) => messages::feed_revoked(found),
+                Err(e) => {
+                    log::error!("Database error: {e}");
+                    messages::internal_error()
+                }
+            };
+
+            reply_and_return!(reply)
+        }
+        Command::SetReminder { name, lead } => {
+            let Some(reminder) = parse_reminder(&lead) else {
+                reply_and_return!(messages::invalid_reminder())
+            };
+
+            let reply = match database
+                .set_subscription_reminder(msg.chat.id, &name, reminder)
+                .await
+            {
+                Ok(found) => messages::reminder_set(found, reminder),
+                Err(e) => {
+                    log::error!("Database error: {e}");
+                    messages::internal_error()
+                }
+            };
+
+            reply_and_return!(reply)
+        }
+        Command::Digest { args } => {
+            let Some(mode) = parse_delivery_mode(&args) else {
+                reply_and_return!(messages::invalid_delivery_mode())
+            };
+
+            let reply = match database
+                .set_delivery_mode(msg.chat.id, mode, chrono::Utc::now())
+                .await
+            {
+                Ok(()) => messages::delivery_mode_set(mode),
+                Err(e) => {
+                    log::error!("Database error: {e}");
+                    messages::internal_error()
+                }
+            };
+
+            reply_and_return!(reply)
+        }
+        Command::Courts { args } => {
+            let query = args.join(" ");
+            let courts = if query.is_empty() {
+                directory::all().iter().collect()
+            } else {
+                directory::search(&query)
+            };
+            reply_fn()(messages::court_list(&courts, &query)).await;
+        }
+        Command::Search { args } => {
+            let query = args.join(" ");
+            if !search::is_valid_query(&query) {
+                reply_and_return!(messages::search_usage())
+            }
+
+            let today = chrono::Utc::now()
+                .with_timezone(&chrono_tz::Europe::Berlin)
+                .date_naive();
+            let sessions = match database.get_all_upcoming_sessions(today).await {
+                Ok(sessions) => sessions,
+                Err(e) => {
+                    log::error!("Database error: {e}");
+                    reply_and_return!(messages::internal_error())
+                }
+            };
+
+            let results = search::search(&query, sessions);
+
+            // refresh the courts with results in the background, so a
+            // repeated search has current data, but without starting new
+            // workers, as they would keep updating their court
+            let mut courts = courts.lock().await;
+            for court in &results {
+                if let Ok(mut court) = courts.get(&court.court) {
+                    if court.is_running() {
+                        court.update(false);
+                    }
+                }
+            }
+            drop(courts);
+
+            reply_fn()(messages::search_results(&query, &results)).await;
+        }
+        Command::ForceUpdate { court } => {
+            // only the admin may accept that a court ran out of sessions
+            if courts.lock().await.is_admin_chat(msg.chat.id) {
+                get_court!(court).accept_drop()
+            } else {
+                get_court!(court).update(true)
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_court_choice(
+    bot: Bot,
+    query: CallbackQuery,
+    courts: Arc<Mutex<Courts>>,
+    database: Database,
+    feed_config: FeedConfig,
+    court_choices: CourtChoices,
+    dialogue_storage: Arc<subscribe_dialogue::Storage>,
+) -> ResponseResult<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    let chosen = match &query.data {
+        Some(data) => court_choices.take(data).await,
+        None => None,
+    };
+
+    let text = match &chosen {
+        Some((_, _, court)) => messages::court_chosen(&court.full_name),
+        None => messages::court_choice_expired(),
+    };
+    if let Some(message) = query.regular_message() {
+        bot.edit_message_text(message.chat.id, message.id, text.to_string())
+            .await?;
+    }
+
+    let Some((msg, mut cmd, court)) = chosen else {
+        return Ok(());
+    };
+    if let Some(command_court) = command_court(&mut cmd) {
+        *command_court = court.id;
+    }
+
+    answer(
+        bot,
+        msg,
+        cmd,
+        courts,
+        database,
+        feed_config,
+        court_choices,
+        dialogue_storage,
+    )
+    .await
+}
+
+async fn handle_subscription_action(
+    bot: Bot,
+    query: CallbackQuery,
+    courts: Arc<Mutex<Courts>>,
+    database: Database,
+    pending_renames: PendingRenames,
+) -> ResponseResult<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    let (Some((action, subscription_id)), Some(message)) = (
+        query.data.as_deref().and_then(subscription_actions::parse),
+        query.regular_message(),
+    ) else {
+        return Ok(());
+    };
+    let chat_id = message.chat.id;
+
+    macro_rules! handle_db_error {
+        ($e:expr) => {
+            match $e {
+                Ok(t) => t,
+                Err(e) => {
+                    log::error!("Database error: {e}");
+                    bot.send_message(chat_id, messages::internal_error().to_string())
+                        .await?;
+                    return Ok(());
+                }
+            }
+        };
+    }
+
+    // the buttons only work in the chat the subscription belongs to
+    let sub = handle_db_error!(database.get_subscription_by_id(subscription_id).await)
+        .filter(|sub| sub.chat_id == chat_id.0);
+    let Some(mut sub) = sub else {
+        bot.edit_message_text(
+            chat_id,
+            message.id,
+            messages::subscription_not_found().to_string(),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    // the list shows the full name of the court
+    let court = sub.court.clone();
+    if let Some(CourtMeta {
+        full_name: Some(full_name),
+        ..
+    }) = handle_db_error!(database.get_court_meta(&sub.court).await)
+    {
+        sub.court = full_name;
+    }
+
+    match action {
+        Action::ShowSessions => {
+            if let Ok(mut court) = courts.lock().await.get(&court) {
+                let reference = sub.reference_filter;
+                court.get_sessions("*".into(), reference, move |reply| async move {
+                    send_chain(&bot, &database, chat_id, reply).await
+                });
+            }
+        }
+        Action::Pause | Action::Resume => {
+            let paused = action == Action::Pause;
+            handle_db_error!(
+                database
+                    .set_subscription_paused(subscription_id, paused)
+                    .await
+            );
+            sub.paused = paused.into();
+            bot.edit_message_text(
+                chat_id,
+                message.id,
+                messages::subscription_entry(&sub).to_string(),
+            )
+            .reply_markup(subscription_actions::keyboard(&sub))
+            .await?;
+        }
+        Action::Delete => {
+            bot.edit_message_text(
+                chat_id,
+                message.id,
+                messages::confirm_unsubscribe(&sub).to_string(),
+            )
+            .reply_markup(subscription_actions::confirm_delete_keyboard(
+                subscription_id,
+            ))
+            .await?;
+        }
+        Action::ConfirmDelete => {
+            let removed =
+                handle_db_error!(database.remove_subscription_by_id(subscription_id).await);
+            bot.edit_message_text(
+                chat_id,
+                message.id,
+                messages::unsubscribed(removed).to_string(),
+            )
+            .await?;
+        }
+        Action::CancelDelete => {
+            bot.edit_message_text(
+                chat_id,
+                message.id,
+                messages::subscription_entry(&sub).to_string(),
+            )
+            .reply_markup(subscription_actions::keyboard(&sub))
+            .await?;
+        }
+        Action::Rename => {
+            let prompt = bot
+                .send_message(chat_id, messages::rename_prompt(&sub.name).to_string())
+                .reply_parameters(ReplyParameters::new(message.id))
+                .reply_markup(ForceReply::new())
+                .await?;
+            pending_renames.add(&prompt, subscription_id).await;
+        }
     }
 
     Ok(())
 }
 
+/// Handles the reply to a rename prompt
+async fn handle_rename(
+    bot: Bot,
+    msg: Message,
+    subscription_id: i64,
+    database: Database,
+) -> ResponseResult<()> {
+    let reply = match msg.text().map(str::trim).filter(|name| !name.is_empty()) {
+        None => messages::invalid_subscription_name(),
+        Some(name) => match database.rename_subscription(subscription_id, name).await {
+            Ok(Some(found)) => messages::subscription_renamed(found, name),
+            Ok(None) => messages::subscription_exists(name),
+            Err(e) => {
+                log::error!("Database error: {e}");
+                messages::internal_error()
+            }
+        },
+    };
+
+    bot.send_message(msg.chat.id, reply.to_string())
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
+    Ok(())
+}
+
+/// Anything the chat sends shows that the bot can write to it again.
+async fn reactivate_chat(update: Update, database: Database) {
+    if !matches!(
+        update.kind,
+        UpdateKind::Message(_) | UpdateKind::CallbackQuery(_)
+    ) {
+        return;
+    }
+    if let Some(chat) = update.chat() {
+        inactive::reactivate_chat(&database, chat.id).await
+    }
+}
+
+async fn handle_member_update(update: ChatMemberUpdated, database: Database) -> ResponseResult<()> {
+    inactive::member_updated(&database, &update).await;
+    Ok(())
+}
+
+/// Reads `ADMIN_CHAT_ID`, the chat that is alerted about scraper problems.
+fn admin_chat_from_env() -> Option<ChatId> {
+    let id = std::env::var("ADMIN_CHAT_ID").ok()?;
+    match id.parse() {
+        Ok(id) => Some(ChatId(id)),
+        Err(e) => {
+            log::error!("Invalid ADMIN_CHAT_ID {id}: {e}");
+            None
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
@@ -231,27 +905,116 @@ async fn main() {
     let bot = bot.parse_mode(ParseMode::MarkdownV2);
     let database_url = std::env::var("DATABASE_URL").unwrap();
     let database = Database::new(&database_url).await.unwrap();
-    let courts = Arc::new(Mutex::new(Courts::new(bot.clone(), database.clone()).await));
+    let admin_chat = admin_chat_from_env();
+    let courts = Arc::new(Mutex::new(
+        Courts::new(bot.clone(), database.clone(), admin_chat).await,
+    ));
 
     let bot_handle = tokio::spawn(bot_worker);
+    let (digest_shutdown, digest_shutdown_rx) = oneshot::channel();
+    let digest_handle = tokio::spawn(digest::run(database.clone(), digest_shutdown_rx));
 
-    Dispatcher::builder(
-        bot,
-        Update::filter_message()
-            .filter_command::<Command>()
-            .endpoint(answer),
-    )
-    .dependencies(deps![courts.clone(), database])
-    .default_handler(|_| async {})
-    .enable_ctrlc_handler()
-    .build()
-    .dispatch()
-    .await;
+    let (delivery_shutdown, delivery_shutdown_rx) = oneshot::channel();
+    let delivery_handle = tokio::spawn(delivery::run(
+        bot.clone(),
+        database.clone(),
+        delivery_shutdown_rx,
+    ));
+    let (inactive_shutdown, inactive_shutdown_rx) = oneshot::channel();
+    let inactive_handle = tokio::spawn(inactive::run(database.clone(), inactive_shutdown_rx));
+
+    let feed_config = FeedConfig::from_env();
+    let (feed_shutdown, feed_shutdown_rx) = oneshot::channel();
+    let feed_handle = feed_config
+        .listen_addr
+        .map(|addr| tokio::spawn(feed::serve(addr, database.clone(), feed_shutdown_rx)));
+
+    let handler = dptree::entry()
+        .inspect_async(reactivate_chat)
+        .branch(Update::filter_my_chat_member().endpoint(handle_member_update))
+        .branch(
+            Update::filter_message()
+                .filter_map(chat_migration)
+                .endpoint(handle_chat_migration),
+        )
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .endpoint(answer),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|query: CallbackQuery| {
+                    query
+                        .data
+                        .as_deref()
+                        .is_some_and(court_choice::is_court_choice)
+                })
+                .endpoint(handle_court_choice),
+        )
+        .branch(
+            Update::filter_message()
+                .filter_map_async(|msg: Message, pending_renames: PendingRenames| async move {
+                    pending_renames.take(&msg).await
+                })
+                .endpoint(handle_rename),
+        )
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, subscribe_dialogue::Storage, subscribe_dialogue::State>()
+                .filter(|state: subscribe_dialogue::State| state.is_active())
+                .endpoint(subscribe_dialogue::handle_message),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|query: CallbackQuery| {
+                    query
+                        .data
+                        .as_deref()
+                        .is_some_and(subscribe_dialogue::is_dialogue_callback)
+                })
+                .endpoint(subscribe_dialogue::handle_callback),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|query: CallbackQuery| {
+                    query
+                        .data
+                        .as_deref()
+                        .is_some_and(subscription_actions::is_subscription_action)
+                })
+                .endpoint(handle_subscription_action),
+        );
+
+    Dispatcher::builder(bot, handler)
+        .dependencies(deps![
+            courts.clone(),
+            database,
+            feed_config,
+            CourtChoices::default(),
+            PendingRenames::default(),
+            subscribe_dialogue::Storage::new()
+        ])
+        .default_handler(|_| async {})
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
 
     let Ok(courts) = Arc::try_unwrap(courts) else {
         panic!("Weird Arc<Courts> flying around")
     };
     drop(courts.into_inner());
+    let _ = digest_shutdown.send(());
+    digest_handle.await.unwrap();
+    let _ = delivery_shutdown.send(());
+    delivery_handle.await.unwrap();
+    let _ = inactive_shutdown.send(());
+    inactive_handle.await.unwrap();
+    let _ = feed_shutdown.send(());
+    if let Some(feed_handle) = feed_handle {
+        feed_handle.await.unwrap();
+    }
 
     // This will finish once all instances of bot are dropped
     bot_handle.await.unwrap();
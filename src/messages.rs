@@ -1,16 +1,15 @@
 mod markdown_string;
 
-use std::collections::HashSet;
-
-use regex::Regex;
+use chrono::{DateTime, Utc};
 
 pub use self::markdown_string::MarkdownString;
-use crate::database::Subscription;
+use crate::changes::SessionChange;
+use crate::courts::MAX_REMINDER_LEAD_DAYS;
+use crate::database::{CachedCourtData, DeliveryMode, PendingChange, Reminder, Subscription};
+use crate::directory::CourtInfo;
+use crate::reference::ReferenceFilter;
 use crate::scraper::{CourtData, Session};
-
-struct ReferenceFilter {
-    regex: Regex,
-}
+use crate::search::CourtResults;
 
 struct Paginator {
     pages: Vec<Vec<MarkdownString>>,
@@ -89,20 +88,14 @@ impl Paginator {
     }
 }
 
-impl ReferenceFilter {
-    fn new(s: &str) -> Self {
-        let regex_pattern = regex::escape(s).replace(r"\*", ".*").replace(r"\?", ".");
-        let regex = Regex::new(&format!("^{regex_pattern}$")).unwrap();
-        Self { regex }
-    }
-
-    fn matches(&self, reference: &str) -> bool {
-        self.regex.is_match(reference)
-    }
-}
-
 pub fn session_info(entry: &Session) -> MarkdownString {
-    let datetime = format!("{}, {}", entry.date.format_localized("%A, %-d. %B %C%y",chrono::Locale::de_DE), entry.time);
+    let datetime = format!(
+        "{}, {}",
+        entry
+            .date
+            .format_localized("%A, %-d. %B %C%y", chrono::Locale::de_DE),
+        entry.time
+    );
 
     let byline = if entry.lawsuit.is_empty() {
         entry.r#type.clone()
@@ -127,10 +120,43 @@ pub fn session_info(entry: &Session) -> MarkdownString {
     result
 }
 
+fn session_changed(old: &Session, new: &Session) -> MarkdownString {
+    fn or_dash(s: &str) -> &str {
+        if s.is_empty() {
+            "–"
+        } else {
+            s
+        }
+    }
+
+    let mut result = MarkdownString::from_str("✏️ Termin geändert\n");
+    for (label, old, new) in [
+        ("Uhrzeit", &old.time, &new.time),
+        ("Saal", &old.hall, &new.hall),
+        ("Art", &old.r#type, &new.r#type),
+        ("Hinweis", &old.note, &new.note),
+    ] {
+        if old != new {
+            result += &format!("{label} geändert: {} → {}\n", or_dash(old), or_dash(new));
+        }
+    }
+    result + &session_info(new)
+}
+
 pub fn invalid_date() -> MarkdownString {
     "Das angegebene Datum ist ungültig.".into()
 }
 
+fn stale_note(stale_since: DateTime<Utc>) -> MarkdownString {
+    let stand = stale_since.with_timezone(&chrono_tz::Europe::Berlin);
+    format!(
+        "⚠️ Stand: {}, Website derzeit nicht erreichbar",
+        stand.format("%d.%m. %H:%M")
+    )
+    .as_str()
+    .into()
+}
+
 fn list_sessions_prefix(court_data: &CourtData, num_items: usize) -> MarkdownString {
     let full_name = MarkdownString::from_str(&court_data.full_name).bold();
     let mut prefix = MarkdownString::new();
@@ -154,8 +180,15 @@ fn list_sessions_prefix(court_data: &CourtData, num_items: usize) -> MarkdownStr
     prefix
 }
 
-pub fn list_sessions(court_data: &Option<CourtData>, reference: &str) -> Vec<MarkdownString> {
-    let Some(court_data) = court_data else {
+pub fn list_sessions(
+    court_data: &Option<CachedCourtData>,
+    reference: &str,
+) -> Vec<MarkdownString> {
+    let Some(CachedCourtData {
+        data: court_data,
+        stale_since,
+    }) = court_data
+    else {
         return vec!["Leider sind keine Informationen für dieses Gericht verfügbar.".into()];
     };
 
@@ -170,7 +203,11 @@ pub fn list_sessions(court_data: &Option<CourtData>, reference: &str) -> Vec<Mar
 
     let mut pages = Paginator::new(20, 4096, "\n\n".into());
 
-    let prefix = list_sessions_prefix(court_data, items.len());
+    let mut prefix = list_sessions_prefix(court_data, items.len());
+    if let Some(stale_since) = stale_since {
+        prefix += "\n";
+        prefix += &stale_note(*stale_since);
+    }
     pages.push(prefix).unwrap();
 
     for item in items {
@@ -184,7 +221,7 @@ pub fn list_sessions(court_data: &Option<CourtData>, reference: &str) -> Vec<Mar
 
 pub fn subscribed(
     name: &str,
-    court_data: &Option<CourtData>,
+    court_data: &Option<CachedCourtData>,
     reference: &str,
 ) -> Vec<MarkdownString> {
     let mut result = "Dein Abo „".into();
@@ -192,7 +229,7 @@ pub fn subscribed(
     result += "” wurde entgegengenommen. ";
 
     match court_data {
-        Some(data) => {
+        Some(CachedCourtData { data, stale_since }) => {
             let reference = ReferenceFilter::new(reference);
             let items: Vec<_> = data
                 .sessions
@@ -205,9 +242,17 @@ pub fn subscribed(
                 0 => {
                     result +=
                         "Zur Zeit gibt es nichts zu melden, aber ich halt dich auf dem Laufenden!";
+                    if let Some(stale_since) = stale_since {
+                        result += "\n";
+                        result += &stale_note(*stale_since);
+                    }
                 }
                 _ => {
                     result += "Hier schon mal eine Liste der anstehenden Termine:";
+                    if let Some(stale_since) = stale_since {
+                        result += "\n";
+                        result += &stale_note(*stale_since);
+                    }
 
                     let mut pages = Paginator::new(20, 4096, "\n\n".into());
 
@@ -234,82 +279,304 @@ pub fn subscribed(
     vec![result]
 }
 
+pub fn search_usage() -> MarkdownString {
+    "Bitte gib ein Aktenzeichen oder einen Suchbegriff an, z.B. \"/search 6 K 1234/23\".".into()
+}
+
+pub fn search_results(query: &str, results: &[CourtResults]) -> Vec<MarkdownString> {
+    if results.is_empty() {
+        return vec![format!(
+            "Zu „{query}” habe ich bei keinem Gericht anstehende Termine gefunden. Ich kenne allerdings nur Termine von Gerichten, die schon einmal abgefragt wurden."
+        )
+        .as_str()
+        .into()];
+    }
+
+    let num_sessions: usize = results.iter().map(|r| r.sessions.len()).sum();
+    let sessions = match num_sessions {
+        1 => "einen anstehenden Termin".to_string(),
+        n => format!("{n} anstehende Termine"),
+    };
+    let courts = match results.len() {
+        1 => "einem Gericht".to_string(),
+        n => format!("{n} Gerichten"),
+    };
+    let mut pages = Paginator::new(20, 4096, "\n\n".into());
+    pages
+        .push(
+            format!("Zu „{query}” habe ich {sessions} bei {courts} gefunden:")
+                .as_str()
+                .into(),
+        )
+        .unwrap();
+
+    for court in results {
+        pages
+            .push(
+                MarkdownString::from_str(&court.full_name)
+                    .bold()
+                    .underline(),
+            )
+            .unwrap_or_else(|_| pages.push("[Eintrag zu lang]".into()).unwrap());
+        for session in &court.sessions {
+            pages
+                .push(session_info(session))
+                .unwrap_or_else(|_| pages.push("[Eintrag zu lang]".into()).unwrap());
+        }
+    }
+
+    pages.get_pages().collect()
+}
+
+pub fn subscribe_usage() -> MarkdownString {
+    "Verwende /subscribe <Name> <Gericht> <Aktenzeichen> oder /subscribe ohne Angaben, dann frage ich dich Schritt für Schritt."
+        .into()
+}
+
+pub fn ask_court() -> MarkdownString {
+    "Für welches Gericht möchtest du ein Abo anlegen? Antworte mit dem Namen, z.B. „VG Köln”. Mit /cancel brichst du ab."
+        .into()
+}
+
+pub fn ask_reference(full_court_name: &str) -> MarkdownString {
+    court_chosen(full_court_name)
+        + "\nWelches Aktenzeichen möchtest du verfolgen? Antworte z.B. mit „6 K 1234/23” oder mit „6 K */24” für alle Verfahren der 6. Kammer aus 2024."
+}
+
+pub fn invalid_reference() -> MarkdownString {
+    "Bitte antworte mit einem Aktenzeichen.".into()
+}
+
+/// Shows which upcoming sessions currently match `reference` and asks for
+/// the name of the subscription. `sessions` is `None` if the court was never
+/// updated.
+pub fn reference_preview(reference: &str, sessions: Option<&[Session]>) -> MarkdownString {
+    const MAX_PREVIEW: usize = 3;
+
+    let mut result = match sessions {
+        None => "Die Termine dieses Gerichts habe ich noch nicht abgerufen, das passiert, sobald das Abo angelegt ist.".into(),
+        Some([]) => MarkdownString::from_str(&format!(
+            "Zur Zeit gibt es keine Termine zu „{reference}”, ich sage dir Bescheid, sobald welche veröffentlicht werden."
+        )),
+        Some(sessions) => {
+            let mut result = MarkdownString::from_str(&format!(
+                "Zur Zeit gibt es {} anstehende Termine zu „{reference}”:",
+                sessions.len()
+            ));
+            for session in sessions.iter().take(MAX_PREVIEW) {
+                result += "\n\n";
+                result += &session_info(session);
+            }
+            if sessions.len() > MAX_PREVIEW {
+                result += "\n\n…";
+            }
+            result
+        }
+    };
+
+    result += "\n\nWie soll das Abo heißen? Antworte auf diese Nachricht mit einem Namen oder nimm den Vorschlag.";
+    result
+}
+
+pub fn subscribe_cancelled(active: bool) -> MarkdownString {
+    if active {
+        "Abgebrochen, es wurde kein Abo angelegt.".into()
+    } else {
+        "Es gibt nichts abzubrechen.".into()
+    }
+}
+
+pub fn text_expected() -> MarkdownString {
+    "Bitte antworte mit Text.".into()
+}
+
 pub fn subscription_exists(name: &str) -> MarkdownString {
     format!("Ein Abo mit dem Namen „{name}” existiert bereits!")
         .as_str()
         .into()
 }
 
-fn subscription_entry(s: &Subscription) -> MarkdownString {
-    MarkdownString::from_str(&s.name).bold()
+pub fn subscription_entry(s: &Subscription) -> MarkdownString {
+    let mut result = MarkdownString::from_str(&s.name).bold();
+    if s.paused != 0 {
+        result += " ⏸ pausiert";
+    }
+    result
         + &format!(
             "\nGericht: {}\nAktenzeichen: {}",
             s.court, s.reference_filter
         )
 }
 
-pub fn list_subscriptions(subscriptions: &[Subscription]) -> Vec<MarkdownString> {
+/// Introduces the list of subscriptions, which are sent as single messages
+/// with buttons.
+pub fn list_subscriptions(subscriptions: &[Subscription]) -> MarkdownString {
     if subscriptions.is_empty() {
-        vec!["Du hast zur Zeit keine Abos am laufen!".into()]
+        "Du hast zur Zeit keine Abos am laufen!".into()
+    } else {
+        "Hier ist eine Liste deiner Abos:".into()
+    }
+}
+
+pub fn confirm_unsubscribe(s: &Subscription) -> MarkdownString {
+    subscription_entry(s) + "\n\nSoll dieses Abo wirklich gelöscht werden?"
+}
+
+pub fn rename_prompt(name: &str) -> MarkdownString {
+    format!("Wie soll das Abo „{name}” heißen? Antworte mit dem neuen Namen.")
+        .as_str()
+        .into()
+}
+
+pub fn invalid_subscription_name() -> MarkdownString {
+    "Bitte antworte mit dem neuen Namen als Text.".into()
+}
+
+pub fn subscription_renamed(found: bool, new_name: &str) -> MarkdownString {
+    if found {
+        MarkdownString::from_str("Abo heißt jetzt „")
+            + &MarkdownString::from_str(new_name).bold()
+            + "” 👍"
     } else {
-        let mut pages = Paginator::new(20, 4096, "\n\n".into());
+        subscription_not_found()
+    }
+}
+
+fn court_entry(court: &CourtInfo) -> MarkdownString {
+    MarkdownString::code_inline(court.id) + &format!(" – {}", court.full_name)
+}
+
+pub fn court_list(courts: &[&CourtInfo], query: &str) -> Vec<MarkdownString> {
+    if courts.is_empty() {
+        return vec![format!("Zu „{query}” wurde kein Gericht gefunden.")
+            .as_str()
+            .into()];
+    }
+
+    let mut pages = Paginator::new(60, 4096, "\n".into());
+    pages
+        .push(
+            "Diese Gerichte kenne ich, den Namen links kannst du in den Befehlen verwenden:\n"
+                .into(),
+        )
+        .unwrap();
+    for court in courts {
         pages
-            .push("Hier ist eine Liste deiner Abos:".into())
-            .unwrap();
-        for sub in subscriptions {
-            pages
-                .push(subscription_entry(sub))
-                .unwrap_or_else(|_| pages.push("[Eintrag zu lang]".into()).unwrap());
+            .push(court_entry(court))
+            .unwrap_or_else(|_| pages.push("[Eintrag zu lang]".into()).unwrap());
+    }
+    pages.get_pages().collect()
+}
+
+pub fn unknown_court(court: &str, suggestions: &[&CourtInfo]) -> MarkdownString {
+    let mut result: MarkdownString = format!("Das Gericht „{court}” kenne ich leider nicht.")
+        .as_str()
+        .into();
+    if suggestions.is_empty() {
+        result += " Mit /courts <Suchbegriff> kannst du nach dem richtigen Namen suchen.";
+    } else {
+        result += " Meintest du:";
+        for suggestion in suggestions {
+            result += "\n";
+            result += &court_entry(suggestion);
         }
-        pages.get_pages().collect()
     }
+    result
+}
+
+pub fn choose_court(input: &str) -> MarkdownString {
+    format!("Welches Gericht meinst du mit „{input}”?")
+        .as_str()
+        .into()
+}
+
+pub fn court_chosen(full_name: &str) -> MarkdownString {
+    MarkdownString::from_str("Gericht: ") + &MarkdownString::from_str(full_name).bold()
+}
+
+pub fn court_choice_expired() -> MarkdownString {
+    "Diese Auswahl ist abgelaufen, bitte sende den Befehl erneut.".into()
+}
+
+pub fn court_too_ambiguous(input: &str) -> MarkdownString {
+    format!(
+        "Zu „{input}” passen zu viele Gerichte. Bitte gib den Namen genauer an, mit /courts {input} findest du alle."
+    )
+    .as_str()
+    .into()
+}
+
+pub fn subscription_not_found() -> MarkdownString {
+    "Es wurde kein Abo mit diesem Namen gefunden.".into()
 }
 
 pub fn unsubscribed(removed: bool) -> MarkdownString {
     if removed {
-        "Abo wurde gelöscht 👍"
+        "Abo wurde gelöscht 👍".into()
     } else {
-        "Es wurde kein Abo mit diesem Namen gefunden."
+        subscription_not_found()
+    }
+}
+
+fn change_info(change: &SessionChange) -> MarkdownString {
+    match change {
+        SessionChange::Added(session) => session_info(session),
+        SessionChange::Changed { old, new } => session_changed(old, new),
+        SessionChange::Cancelled(session) => {
+            MarkdownString::from_str("🚫 Termin aufgehoben\n") + &session_info(session)
+        }
     }
-    .into()
 }
 
 pub fn sessions_updated(
-    old_sessions: &[Session],
-    new_sessions: &[Session],
+    changes: &[SessionChange],
     full_court_name: &str,
     subscription_name: &str,
-    reference_filter: &str,
 ) -> Vec<MarkdownString> {
-    let reference = ReferenceFilter::new(reference_filter);
-    let old_sessions: HashSet<_> = old_sessions.iter().collect();
-
-    let items: Vec<_> = new_sessions
-        .iter()
-        .filter(|session| reference.matches(&session.reference) && !old_sessions.contains(session))
-        .map(session_info)
-        .collect();
-
-    if items.is_empty() {
+    if changes.is_empty() {
         return vec![];
     }
 
+    let (mut added, mut changed, mut cancelled) = (0, 0, 0);
+    for change in changes {
+        match change {
+            SessionChange::Added(_) => added += 1,
+            SessionChange::Changed { .. } => changed += 1,
+            SessionChange::Cancelled(_) => cancelled += 1,
+        }
+    }
+
     let mut prefix = MarkdownString::new();
     prefix += "🔔 Zu deinem Abo „";
     prefix += &MarkdownString::from_str(subscription_name).bold();
-    if items.len() == 1 {
-        prefix += &format!("” ({full_court_name}) wurde ein neuer Termin veröffentlicht!");
-    } else {
-        prefix += &format!(
-            "” ({full_court_name}) wurden {} neue Termine veröffentlicht!",
-            items.len()
-        );
+    match (added, changed, cancelled) {
+        (1, 0, 0) => {
+            prefix += &format!("” ({full_court_name}) wurde ein neuer Termin veröffentlicht!");
+        }
+        (n, 0, 0) => {
+            prefix += &format!("” ({full_court_name}) wurden {n} neue Termine veröffentlicht!");
+        }
+        (0, 1, 0) => {
+            prefix += &format!("” ({full_court_name}) wurde ein Termin geändert!");
+        }
+        (0, n, 0) => {
+            prefix += &format!("” ({full_court_name}) wurden {n} Termine geändert!");
+        }
+        (0, 0, 1) => {
+            prefix += &format!("” ({full_court_name}) wurde ein Termin aufgehoben!");
+        }
+        (0, 0, n) => {
+            prefix += &format!("” ({full_court_name}) wurden {n} Termine aufgehoben!");
+        }
+        (a, b, c) => {
+            prefix += &format!("” ({full_court_name}) gibt es {} Änderungen:", a + b + c);
+        }
     }
 
     let mut pages = Paginator::new(20, 4096, "\n\n".into());
 
     pages.push(prefix).unwrap();
-    for item in items {
+    for item in changes.iter().map(change_info) {
         pages
             .push(item)
             .unwrap_or_else(|_| pages.push("[Eintrag zu lang]".into()).unwrap());
@@ -317,28 +584,211 @@ pub fn sessions_updated(
     pages.get_pages().collect()
 }
 
+pub fn digest(pending: &[PendingChange]) -> Vec<MarkdownString> {
+    if pending.is_empty() {
+        return vec![];
+    }
+
+    let mut pages = Paginator::new(20, 4096, "\n\n".into());
+
+    let mut prefix = MarkdownString::from_str("📰 Deine Zusammenfassung: ");
+    if pending.len() == 1 {
+        prefix += "Es gibt eine Änderung zu deinen Abos.";
+    } else {
+        prefix += &format!("Es gibt {} Änderungen zu deinen Abos.", pending.len());
+    }
+    pages.push(prefix).unwrap();
+
+    let mut by_subscription: Vec<(&str, &str, Vec<&SessionChange>)> = vec![];
+    for p in pending {
+        match by_subscription
+            .iter_mut()
+            .find(|(name, court, _)| *name == p.subscription_name && *court == p.court_name)
+        {
+            Some((_, _, changes)) => changes.push(&p.change),
+            None => by_subscription.push((&p.subscription_name, &p.court_name, vec![&p.change])),
+        }
+    }
+
+    for (subscription_name, court_name, changes) in by_subscription {
+        let mut header = MarkdownString::from_str("Abo „");
+        header += &MarkdownString::from_str(subscription_name).bold();
+        header += &format!("” ({court_name}):");
+        pages.push(header).unwrap();
+
+        for item in changes.into_iter().map(change_info) {
+            pages
+                .push(item)
+                .unwrap_or_else(|_| pages.push("[Eintrag zu lang]".into()).unwrap());
+        }
+    }
+
+    pages.get_pages().collect()
+}
+
+pub fn delivery_mode_set(mode: DeliveryMode) -> MarkdownString {
+    let weekday = |weekday: u8| {
+        [
+            "Montag",
+            "Dienstag",
+            "Mittwoch",
+            "Donnerstag",
+            "Freitag",
+            "Samstag",
+            "Sonntag",
+        ][weekday as usize % 7]
+    };
+
+    match mode {
+        DeliveryMode::Immediate => {
+            "Du wirst ab jetzt sofort über Änderungen benachrichtigt 👍".into()
+        }
+        DeliveryMode::Daily { time } => format!(
+            "Du bekommst ab jetzt täglich um {} Uhr eine Zusammenfassung 👍",
+            time.format("%H:%M")
+        )
+        .as_str()
+        .into(),
+        DeliveryMode::Weekly { weekday: day, time } => format!(
+            "Du bekommst ab jetzt jeden {} um {} Uhr eine Zusammenfassung 👍",
+            weekday(day),
+            time.format("%H:%M")
+        )
+        .as_str()
+        .into(),
+    }
+}
+
+pub fn invalid_delivery_mode() -> MarkdownString {
+    "Ungültige Angabe! Beispiele: \"sofort\", \"täglich 8:00\" oder \"wöchentlich Montag 8:00\"."
+        .into()
+}
+
+pub fn session_reminder(
+    session: &Session,
+    full_court_name: &str,
+    subscription_name: &str,
+) -> Vec<MarkdownString> {
+    let mut result = MarkdownString::from_str("⏰ Erinnerung zu deinem Abo „");
+    result += &MarkdownString::from_str(subscription_name).bold();
+    result += &format!("” ({full_court_name}):\n\n");
+    result += &session_info(session);
+
+    vec![result]
+}
+
+pub fn reminder_set(found: bool, reminder: Reminder) -> MarkdownString {
+    if !found {
+        return subscription_not_found();
+    }
+
+    match reminder {
+        Reminder::Off => "Erinnerungen für dieses Abo sind jetzt ausgeschaltet.".into(),
+        Reminder::EveningBefore => {
+            "Du wirst jetzt am Vorabend um 18 Uhr an die Termine erinnert 👍".into()
+        }
+        Reminder::MinutesBefore(minutes) => {
+            let lead = if minutes % 60 == 0 {
+                format!("{} Stunde(n)", minutes / 60)
+            } else {
+                format!("{minutes} Minute(n)")
+            };
+            format!("Du wirst jetzt {lead} vor Beginn an die Termine erinnert 👍")
+                .as_str()
+                .into()
+        }
+    }
+}
+
+pub fn invalid_reminder() -> MarkdownString {
+    format!(
+        "Ungültiger Vorlauf! Erlaubt sind \"aus\", \"abends\" oder eine Zeitspanne wie \"2h\" oder \"30min\" (höchstens {MAX_REMINDER_LEAD_DAYS} Tage)."
+    )
+    .as_str()
+    .into()
+}
+
+pub fn ical_usage() -> MarkdownString {
+    "Bitte gib entweder den Namen eines Abos oder Gericht und Aktenzeichen an.".into()
+}
+
+pub fn feed_url(name: &str, url: &str) -> MarkdownString {
+    let mut result = MarkdownString::from_str("Kalender-Feed für dein Abo „");
+    result += &MarkdownString::from_str(name).bold();
+    result += "”:\n";
+    result += &MarkdownString::code_inline(url);
+    result += "\n\nDiese Adresse kannst du in deiner Kalender-App abonnieren. Wer sie kennt, kann deine Termine sehen, mit /revoke_feed kannst du sie ungültig machen.";
+    result
+}
+
+pub fn feed_revoked(found: bool) -> MarkdownString {
+    if found {
+        "Der Kalender-Feed wurde widerrufen 👍".into()
+    } else {
+        subscription_not_found()
+    }
+}
+
+pub fn feeds_disabled() -> MarkdownString {
+    "Kalender-Feeds sind auf diesem Bot leider nicht verfügbar.".into()
+}
+
 pub fn help() -> MarkdownString {
     let help = "
 Unterstützte Befehle:
 /help
 /get_sessions <Gericht> <Datum> <Aktenzeichen>
 /subscribe <beliebiger Name> <Gericht> <Aktenzeichen>
+/subscribe (fragt Schritt für Schritt)
+/cancel
 /list_subscriptions
 /unsubscribe <Name>
+/ical <Gericht> <Aktenzeichen>
+/ical <Abo-Name>
+/feed <Abo-Name>
+/revoke_feed <Abo-Name>
+/set_reminder <Name> <Vorlauf>
+/digest <sofort|täglich|wöchentlich> [Wochentag] [Uhrzeit]
+/courts [Suchbegriff]
+/search <Aktenzeichen|Stichwort>
 
 Wenn ein Parameter Leerzeichen enthält, muss er in Anführungszeichen gesetzt werden.
 
-Der Name des Gerichts muss sein wie in der URL der Website, also z.B. \"vg-koeln\".
+Das Gericht kannst du mit Namen angeben, z.B. \"VG Köln\" oder \"Verwaltungsgericht Köln\", oder wie in der URL der Website, also \"vg-koeln\". Mit /courts <Suchbegriff> kannst du nach Gerichten suchen.
 
 Das Datum kann auch \"*\" sein, um jedes Datum zu erfassen.
 
 Im Aktenzeichen steht \"?\" für ein beliebiges einzelnes Zeichen,  \"*\" für eine beliebige Zeichenkette.
+Leerzeichen und führende Nullen im Aktenzeichen spielen keine Rolle, \"1 K 123/24\" findet also auch \"1K 0123/24\". Platzhalter gehen auch für einzelne Teile, z.B. \"6 K */24\".
+
+Als Vorlauf für Erinnerungen sind \"aus\", \"abends\" (Standard, am Vorabend um 18 Uhr) oder eine Zeitspanne vor Beginn wie \"2h\" oder \"30min\" möglich.
+
+Unter jedem Abo in /list_subscriptions findest du Knöpfe, um seine Termine anzuzeigen oder es zu pausieren, zu löschen oder umzubenennen.
+
+Mit /digest kannst du statt sofortiger Benachrichtigungen eine tägliche oder wöchentliche Zusammenfassung erhalten, z.B. \"/digest täglich 8:00\" oder \"/digest wöchentlich Montag 8:00\".
 
 Keine Gewähr für verpasste Termine!";
 
     help.into()
 }
 
+pub fn scraper_alert(court: &str, error: &str) -> MarkdownString {
+    let mut result = MarkdownString::from_str("🚨 Die Website von ");
+    result += &MarkdownString::code_inline(court);
+    result += format!(
+        " hat sich anscheinend verändert, die Termine werden bis auf Weiteres nicht aktualisiert.\nFehler: {error}"
+    )
+    .as_str();
+    result
+}
+
+pub fn scraper_alert_resolved(court: &str) -> MarkdownString {
+    let mut result = MarkdownString::from_str("✅ Die Website von ");
+    result += &MarkdownString::code_inline(court);
+    result += " kann wieder gelesen werden.";
+    result
+}
+
 pub fn internal_error() -> MarkdownString {
     "Sorry, ein interner Fehler ist aufgetreten :((".into()
 }
@@ -0,0 +1,220 @@
+//! German court file numbers (Aktenzeichen) like "6 K 1234/23" and the
+//! patterns subscriptions use to select them.
+
+use std::fmt;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref REFERENCE_REGEX: Regex = Regex::new(
+        r"^(?:(?P<chamber>\d+(?:-\d+)?|[IVXL]+-\d+|[IVXL]+\s)\s*)?(?P<register>\p{L}+)\s*(?P<number>\d+)\s*/\s*(?P<year>\d{4}|\d{2})(?P<suffix>.*)$"
+    )
+    .unwrap();
+    static ref PATTERN_REGEX: Regex = Regex::new(
+        r"^(?:(?P<chamber>[\d*?]+(?:-[\d*?]+)?|[IVXL*?]+-[\d*?]+|[IVXL*?]+\s)\s*)?(?P<register>[\p{L}*?]+)\s*(?P<number>[\d*?]+)\s*/\s*(?P<year>[\d*?]+)(?P<suffix>.*)$"
+    )
+    .unwrap();
+}
+
+/// A parsed court file number, consisting of the chamber or senate, the
+/// register sign, a running number and the year, e.g. "6 K 1234/23".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aktenzeichen {
+    /// Kammer or Senat, e.g. "6" or "I-7", possibly empty
+    pub chamber: String,
+    /// Registerzeichen, e.g. "K" or "KLs"
+    pub register: String,
+    pub number: u32,
+    /// Two digit year
+    pub year: u32,
+    /// Anything after the year, e.g. ".A" for asylum cases
+    pub suffix: String,
+}
+
+impl Aktenzeichen {
+    pub fn parse(s: &str) -> Option<Self> {
+        let captures = REFERENCE_REGEX.captures(s.trim())?;
+        let year: u32 = captures["year"].parse().ok()?;
+
+        Some(Self {
+            chamber: captures
+                .name("chamber")
+                .map_or("", |c| c.as_str().trim())
+                .to_string(),
+            register: captures["register"].to_string(),
+            number: captures["number"].parse().ok()?,
+            year: year % 100,
+            suffix: captures["suffix"].trim().to_string(),
+        })
+    }
+
+    /// The normalized form, e.g. "1 K 123/24" for "1K 0123/2024"
+    pub fn normalized(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Aktenzeichen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.chamber.is_empty() {
+            write!(f, "{} ", self.chamber)?;
+        }
+        write!(
+            f,
+            "{} {}/{:02}{}",
+            self.register, self.number, self.year, self.suffix
+        )
+    }
+}
+
+/// A glob pattern, supporting `*` and `?`
+struct Glob(Regex);
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        let regex_pattern = regex::escape(pattern)
+            .replace(r"\*", ".*")
+            .replace(r"\?", ".");
+        Self(Regex::new(&format!("(?i)^{regex_pattern}$")).unwrap())
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        self.0.is_match(s)
+    }
+}
+
+struct ComponentPattern {
+    chamber: Glob,
+    register: Glob,
+    number: Glob,
+    year: Glob,
+    suffix: Glob,
+}
+
+impl ComponentPattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let captures = PATTERN_REGEX.captures(pattern.trim())?;
+
+        // leading zeros and the century are insignificant, unless they
+        // contain wildcards
+        let number = &captures["number"];
+        let number = number
+            .parse::<u32>()
+            .map_or_else(|_| number.to_string(), |number| number.to_string());
+        let year = &captures["year"];
+        let year = match year.parse::<u32>() {
+            Ok(year) => format!("{:02}", year % 100),
+            Err(_) if year.len() == 4 => year[2..].to_string(),
+            Err(_) => year.to_string(),
+        };
+
+        Some(Self {
+            chamber: Glob::new(captures.name("chamber").map_or("", |c| c.as_str().trim())),
+            register: Glob::new(&captures["register"]),
+            number: Glob::new(&number),
+            year: Glob::new(&year),
+            suffix: Glob::new(captures["suffix"].trim()),
+        })
+    }
+
+    fn matches(&self, reference: &Aktenzeichen) -> bool {
+        self.chamber.matches(&reference.chamber)
+            && self.register.matches(&reference.register)
+            && self.number.matches(&reference.number.to_string())
+            && self.year.matches(&format!("{:02}", reference.year))
+            && self.suffix.matches(&reference.suffix)
+    }
+}
+
+/// Selects sessions by their reference. Patterns that look like a file
+/// number are matched on the normalized components, so "1 K 123/24" also
+/// matches "1K 0123/24", and wildcards can be used per component, e.g.
+/// "6 K */24". Other patterns are matched as globs, ignoring whitespace.
+pub struct ReferenceFilter {
+    components: Option<ComponentPattern>,
+    glob: Glob,
+}
+
+fn without_whitespace(s: &str) -> String {
+    s.split_whitespace().collect()
+}
+
+impl ReferenceFilter {
+    pub fn new(s: &str) -> Self {
+        Self {
+            components: ComponentPattern::parse(s),
+            glob: Glob::new(&without_whitespace(s)),
+        }
+    }
+
+    pub fn matches(&self, reference: &str) -> bool {
+        let parsed = Aktenzeichen::parse(reference);
+
+        if let (Some(components), Some(parsed)) = (&self.components, &parsed) {
+            return components.matches(parsed);
+        }
+
+        self.glob.matches(&without_whitespace(reference))
+            || parsed
+                .is_some_and(|parsed| self.glob.matches(&without_whitespace(&parsed.normalized())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parsed = Aktenzeichen::parse("8 K 5501/22.A").unwrap();
+        assert_eq!(parsed.chamber, "8");
+        assert_eq!(parsed.register, "K");
+        assert_eq!(parsed.number, 5501);
+        assert_eq!(parsed.year, 22);
+        assert_eq!(parsed.suffix, ".A");
+
+        assert_eq!(Aktenzeichen::parse("I-7 U 45/23").unwrap().chamber, "I-7");
+        assert_eq!(
+            Aktenzeichen::parse("IV ZR 12/2023").unwrap().normalized(),
+            "IV ZR 12/23"
+        );
+        assert_eq!(Aktenzeichen::parse("21 KLs 3/24").unwrap().register, "KLs");
+        assert_eq!(Aktenzeichen::parse("nach Aufruf"), None);
+    }
+
+    #[test]
+    fn normalized() {
+        for reference in ["1 K 123/24", "1K 123/24", "1 K 0123/24", " 1 K 123 / 2024 "] {
+            let parsed = Aktenzeichen::parse(reference).unwrap();
+            assert_eq!(parsed.normalized(), "1 K 123/24", "{reference}");
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let filter = ReferenceFilter::new("1 K 123/24");
+        assert!(filter.matches("1K 123/24"));
+        assert!(filter.matches("1 K 0123/24"));
+        assert!(filter.matches("1 k 123/24"));
+        assert!(!filter.matches("1 K 1234/24"));
+        assert!(!filter.matches("11 K 123/24"));
+
+        let filter = ReferenceFilter::new("6 K */24");
+        assert!(filter.matches("6 K 1234/24"));
+        assert!(filter.matches("6K 17/2024"));
+        assert!(!filter.matches("6 K 1234/23"));
+
+        let filter = ReferenceFilter::new("? K 1*/2?");
+        assert!(filter.matches("6 K 1234/23"));
+        assert!(!filter.matches("16 K 1234/23"));
+    }
+
+    #[test]
+    fn glob_fallback() {
+        assert!(ReferenceFilter::new("*").matches("nach Aufruf"));
+        assert!(ReferenceFilter::new("6 K *").matches("6K 1234/23"));
+        assert!(ReferenceFilter::new("*K*").matches("6 K 1234/23"));
+        assert!(!ReferenceFilter::new("6 K *").matches("6 L 1234/23"));
+    }
+}
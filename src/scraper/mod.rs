@@ -0,0 +1,385 @@
+//! Court websites the session data can be retrieved from
+
+mod nrw;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use futures_core::future::BoxFuture;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to retrieve website: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    #[error("invalid website content: {0}")]
+    Parse(Cow<'static, str>),
+    #[error("unexpected HTTP status: {0}")]
+    HttpStatus(reqwest::StatusCode),
+}
+
+impl Error {
+    /// Whether retrying the request might succeed
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Reqwest(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            Error::HttpStatus(status) => {
+                status.is_server_error()
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+                    || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            Error::Parse(_) => false,
+        }
+    }
+
+    /// Whether the website was reachable, but didn't look as expected, e.g.
+    /// after a redesign
+    pub fn is_content_error(&self) -> bool {
+        matches!(self, Error::Parse(_))
+    }
+}
+
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Validators of a previously retrieved page
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedPage {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Hex encoded SHA-256 of the page content
+    pub content_hash: String,
+}
+
+/// Cached pages by URL
+pub type PageCache = HashMap<String, CachedPage>;
+
+enum Fetched {
+    /// The page hasn't changed since it was cached
+    Unchanged(CachedPage),
+    Changed(String, CachedPage),
+}
+
+async fn fetch_once(
+    client: &reqwest::Client,
+    url: &str,
+    cached: Option<&CachedPage>,
+) -> Result<Fetched, Error> {
+    let mut request = client.get(url);
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        if let Some(cached) = cached {
+            return Ok(Fetched::Unchanged(cached.clone()));
+        }
+    }
+    // error pages must not be mistaken for content
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+
+    let get_header = |name| {
+        let value = response.headers().get(name)?.to_str().ok()?;
+        Some(value.to_owned())
+    };
+    let etag = get_header(header::ETAG);
+    let last_modified = get_header(header::LAST_MODIFIED);
+
+    let body = response.text().await?;
+    let page = CachedPage {
+        etag,
+        last_modified,
+        content_hash: format!("{:x}", Sha256::digest(&body)),
+    };
+
+    if cached.is_some_and(|cached| cached.content_hash == page.content_hash) {
+        Ok(Fetched::Unchanged(page))
+    } else {
+        Ok(Fetched::Changed(body, page))
+    }
+}
+
+/// Retrieves a page, retrying transient errors with jittered exponential
+/// backoff. If `cached` is given, the request is conditional.
+async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    cached: Option<&CachedPage>,
+) -> Result<Fetched, Error> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match fetch_once(client, url, cached).await {
+            Err(e) if e.is_transient() && attempt < MAX_ATTEMPTS => {
+                let delay = backoff / 2 + backoff.mul_f64(rand::random::<f64>() / 2.0);
+                log::info!("Fetching {url} failed ({e}), retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Session {
+    pub date: NaiveDate,
+    /// Time as published, free text like "09:30 Uhr" or "10:00 - 11:00"
+    pub time: String,
+    /// Parsed from `time` by [`parse_times`]
+    #[serde(default)]
+    pub start_time: Option<NaiveTime>,
+    #[serde(default)]
+    pub end_time: Option<NaiveTime>,
+    pub r#type: String,
+    pub lawsuit: String,
+    pub hall: String,
+    pub reference: String,
+    pub note: String,
+}
+
+/// Identifies a session across scrapes, even if its time, hall or note changed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub date: NaiveDate,
+    pub reference: String,
+    pub lawsuit: String,
+}
+
+/// Best effort guess of start and end time, as the published time is free
+/// text, e.g. "09:30 Uhr", "9 Uhr" or "10.00 - 11.30".
+pub fn parse_times(text: &str) -> (Option<NaiveTime>, Option<NaiveTime>) {
+    lazy_static! {
+        static ref TIME_REGEX: Regex = Regex::new(r"(\d{1,2})(?:[:.](\d{2}))?(\s*Uhr)?").unwrap();
+    }
+
+    let is_digit = |c: char| c.is_ascii_digit();
+    let mut times = TIME_REGEX.captures_iter(text).filter_map(|captures| {
+        let range = captures.get(0)?.range();
+        // skip numbers that belong to something else, e.g. a date
+        let before = &text[..range.start];
+        let after = &text[range.end..];
+        if before.ends_with(|c| is_digit(c) || c == '.' || c == ':')
+            || after.starts_with(is_digit)
+            || (after.starts_with(['.', ':']) && after[1..].starts_with(is_digit))
+        {
+            return None;
+        }
+
+        let minutes = match (captures.get(2), captures.get(3)) {
+            (Some(minutes), _) => minutes.as_str().parse().ok()?,
+            (None, Some(_)) => 0,
+            // a lone number is no time
+            (None, None) => return None,
+        };
+        NaiveTime::from_hms_opt(captures[1].parse().ok()?, minutes, 0)
+    });
+
+    let start = times.next();
+    let end = times
+        .next()
+        .filter(|&end| start.is_some_and(|start| start < end));
+    (start, end)
+}
+
+/// Collapses all whitespace (including line breaks and non-breaking spaces)
+/// to single spaces, so the values don't depend on the formatting of the
+/// HTML source.
+pub fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl Session {
+    pub fn key(&self) -> SessionKey {
+        SessionKey {
+            date: self.date,
+            reference: self.reference.clone(),
+            lawsuit: self.lawsuit.clone(),
+        }
+    }
+
+    /// Brings a session stored by an older version of the scraper into the
+    /// form the current one produces. Returns whether anything changed.
+    pub fn normalize(&mut self) -> bool {
+        let before = self.clone();
+        for text in [
+            &mut self.time,
+            &mut self.r#type,
+            &mut self.lawsuit,
+            &mut self.hall,
+            &mut self.reference,
+            &mut self.note,
+        ] {
+            *text = normalize_text(text);
+        }
+        (self.start_time, self.end_time) = parse_times(&self.time);
+
+        *self != before
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CourtData {
+    pub full_name: String,
+    pub sessions: Vec<Session>,
+}
+
+/// The result of scraping a court's website.
+#[derive(Debug, Clone)]
+pub struct CourtScrape {
+    /// Contains only the sessions of pages that changed
+    pub data: CourtData,
+    /// Dates whose sessions could not be retrieved, even after retrying.
+    pub failed_dates: Vec<NaiveDate>,
+    /// Dates whose page is unchanged, so the stored sessions are still valid
+    pub unchanged_dates: Vec<NaiveDate>,
+    /// Whether no page at all has changed
+    pub unchanged: bool,
+    /// Validators of all pages belonging to the court
+    pub pages: Vec<(String, CachedPage)>,
+}
+
+impl CourtScrape {
+    /// A partial scrape is missing sessions, so it must not be used to decide
+    /// which sessions have been removed or cancelled.
+    pub fn is_partial(&self) -> bool {
+        !self.failed_dates.is_empty()
+    }
+}
+
+/// A website publishing the sessions of one or more courts.
+pub trait CourtSource: Send + Sync {
+    /// `court` is the court id without the source prefix.
+    fn is_valid_court(&self, court: &str) -> bool;
+
+    /// Pages found in `cache` may be skipped if they haven't changed.
+    fn get_court_data<'a>(
+        &'a self,
+        court: &'a str,
+        cache: &'a PageCache,
+    ) -> BoxFuture<'a, Result<CourtScrape, Error>>;
+}
+
+/// Court ids look like `<prefix>:<court>`, the prefix selecting the source.
+/// Ids without prefix belong to the NRW justice portal.
+struct Registry {
+    default: Box<dyn CourtSource>,
+    by_prefix: HashMap<&'static str, Box<dyn CourtSource>>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        Self {
+            default: Box::new(nrw::NrwJustiz::from_env()),
+            by_prefix: HashMap::new(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+}
+
+/// Returns the source responsible for a court and the court id to use with
+/// it, or `None` if the court id is invalid.
+pub fn lookup(court_id: &str) -> Option<(&'static dyn CourtSource, &str)> {
+    let (source, court) = match court_id.split_once(':') {
+        Some((prefix, court)) => (REGISTRY.by_prefix.get(prefix)?, court),
+        None => (&REGISTRY.default, court_id),
+    };
+
+    source.is_valid_court(court).then_some((&**source, court))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(h, m, 0)
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_times("09:30 Uhr"), (time(9, 30), None));
+        assert_eq!(parse_times("9.30"), (time(9, 30), None));
+        assert_eq!(parse_times("ab 9 Uhr."), (time(9, 0), None));
+        assert_eq!(parse_times("10:00 - 11:30"), (time(10, 0), time(11, 30)));
+        assert_eq!(
+            parse_times("10.00 bis 11.00 Uhr"),
+            (time(10, 0), time(11, 0))
+        );
+        assert_eq!(
+            parse_times("14:00 (Fortsetzung 12:00)"),
+            (time(14, 0), None)
+        );
+    }
+
+    #[test]
+    fn normalize_stored_session() {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 2).unwrap();
+        // as stored before whitespace was collapsed
+        let mut stored = Session {
+            date,
+            time: "09:30\u{a0}Uhr".to_string(),
+            start_time: None,
+            end_time: None,
+            r#type: " Verhandlung ".to_string(),
+            lawsuit: "A\n   ./. B".to_string(),
+            hall: "Saal\n   A 101".to_string(),
+            reference: "1 K\u{a0}1/24".to_string(),
+            note: String::new(),
+        };
+        let scraped = Session {
+            date,
+            time: "09:30 Uhr".to_string(),
+            start_time: time(9, 30),
+            end_time: None,
+            r#type: "Verhandlung".to_string(),
+            lawsuit: "A ./. B".to_string(),
+            hall: "Saal A 101".to_string(),
+            reference: "1 K 1/24".to_string(),
+            note: String::new(),
+        };
+
+        assert!(stored.normalize());
+        assert_eq!(stored, scraped);
+        assert_eq!(stored.key(), scraped.key());
+        assert!(!stored.normalize());
+    }
+
+    #[test]
+    fn transient_errors() {
+        let status = |code| Error::HttpStatus(StatusCode::from_u16(code).unwrap());
+        assert!(status(503).is_transient());
+        assert!(status(429).is_transient());
+        assert!(status(408).is_transient());
+        assert!(!status(404).is_transient());
+        assert!(!status(403).is_content_error());
+        assert!(!Error::Parse("".into()).is_transient());
+    }
+
+    #[test]
+    fn no_times() {
+        assert_eq!(parse_times(""), (None, None));
+        assert_eq!(parse_times("nach Aufruf"), (None, None));
+        assert_eq!(parse_times("Fortsetzung vom 02.09.2024"), (None, None));
+        assert_eq!(parse_times("25:00"), (None, None));
+    }
+}
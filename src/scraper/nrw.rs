@@ -0,0 +1,305 @@
+//! Scraper for the court websites of North Rhine-Westphalia (justiz.nrw.de)
+
+use std::time::Duration;
+
+use chrono::prelude::*;
+use chrono_tz::Europe;
+use futures_core::future::BoxFuture;
+use futures_util::{stream, StreamExt};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::StatusCode;
+use scraper::{ElementRef, Html, Selector};
+
+use super::{CachedPage, CourtData, CourtScrape, CourtSource, Error, Fetched, PageCache, Session};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_URL_TEMPLATE: &str = "https://www.{name}.nrw.de/behoerde/sitzungstermine/index.php";
+
+fn get_url(url_template: &str, name: &str) -> String {
+    url_template.replace("{name}", name)
+}
+
+fn get_date_url(index_url: &str, date_unix: i64) -> String {
+    let separator = if index_url.contains('?') { '&' } else { '?' };
+    format!("{index_url}{separator}startDate={date_unix}&termsPerPage=0")
+}
+
+fn extract_text(e: &ElementRef) -> String {
+    super::normalize_text(&e.text().collect::<Vec<_>>().concat())
+}
+
+lazy_static! {
+    static ref TABLE_SELECTOR: Selector = Selector::parse("table#sitzungsTermineTable").unwrap();
+    static ref TR_SELECTOR: Selector =
+        Selector::parse("table#sitzungsTermineTable tr[id].dataRow").unwrap();
+    static ref NAME_SELECTOR: Selector = Selector::parse("meta[name=Copyright]").unwrap();
+    static ref DATE_SELECT_SELECTOR: Selector = Selector::parse("select#startDate").unwrap();
+    static ref DATES_SELECTOR: Selector = Selector::parse("#startDate > option").unwrap();
+}
+
+struct IndexPageContent {
+    full_name: String,
+    urls: Vec<(NaiveDate, String)>,
+}
+
+fn parse_index_page(html: &str, url: &str) -> Result<IndexPageContent, Error> {
+    let document = Html::parse_document(html);
+
+    for error in &document.errors {
+        log::info!("Parser error: {error}");
+    }
+
+    let full_name = document
+        .select(&NAME_SELECTOR)
+        .next()
+        .ok_or(Error::Parse("meta tag not on site".into()))?
+        .attr("content")
+        .ok_or(Error::Parse("invalid meta tag".into()))?
+        .trim()
+        .to_string();
+
+    // a court without sessions has an empty selection, but never none at all
+    if document.select(&DATE_SELECT_SELECTOR).next().is_none() {
+        return Err(Error::Parse("date selection not on site".into()));
+    }
+
+    let urls = document
+        .select(&DATES_SELECTOR)
+        .filter_map(|elem| {
+            let date_unix = elem.value().attr("value")?;
+            let date_unix: i64 = match date_unix.trim().parse() {
+                Ok(timestamp) => timestamp,
+                Err(_) => {
+                    log::warn!("Timestamp {date_unix} is no valid number.");
+                    return None;
+                }
+            };
+
+            let date = DateTime::from_timestamp(date_unix, 0)?
+                .with_timezone(&Europe::Berlin)
+                .date_naive();
+            let url = get_date_url(url, date_unix);
+            Some((date, url))
+        })
+        .collect();
+
+    Ok(IndexPageContent { full_name, urls })
+}
+
+fn parse_row(tr: ElementRef, date: NaiveDate) -> Session {
+    macro_rules! get_cell {
+        ($sel:literal) => {{
+            lazy_static! {
+                static ref SELECTOR: Selector = Selector::parse($sel).unwrap();
+            }
+
+            match tr.select(&SELECTOR).next() {
+                Some(td) => extract_text(&td),
+                None => String::new(),
+            }
+        }};
+    }
+
+    let time = get_cell!("td.termDate");
+    let (start_time, end_time) = super::parse_times(&time);
+
+    Session {
+        date,
+        time,
+        start_time,
+        end_time,
+        r#type: get_cell!("td.termType"),
+        lawsuit: get_cell!("td.termLawsuit"),
+        hall: get_cell!("td.termHall"),
+        reference: get_cell!("td.termReference"),
+        note: get_cell!("td.termNote"),
+    }
+}
+
+fn parse_table(html: &str, date: NaiveDate) -> Result<Vec<Session>, Error> {
+    let document = Html::parse_document(html);
+
+    for error in &document.errors {
+        log::info!("Parser error: {error}");
+    }
+
+    // otherwise a changed layout would look like a day without sessions
+    if document.select(&TABLE_SELECTOR).next().is_none() {
+        return Err(Error::Parse("session table not on site".into()));
+    }
+
+    let entries: Vec<_> = document
+        .select(&TR_SELECTOR)
+        .map(|tr| parse_row(tr, date))
+        .collect();
+
+    log::debug!("Got {} entries", entries.len());
+
+    Ok(entries)
+}
+
+/// Returns `None` instead of the sessions if the page is unchanged.
+async fn get_sessions(
+    url: &str,
+    date: NaiveDate,
+    client: &reqwest::Client,
+    cached: Option<&CachedPage>,
+) -> Result<(Option<Vec<Session>>, CachedPage), Error> {
+    log::info!("Fetch url {url}");
+    match super::fetch(client, url, cached).await? {
+        Fetched::Changed(html, page) => Ok((Some(parse_table(&html, date)?), page)),
+        Fetched::Unchanged(page) => {
+            log::debug!("{url} is unchanged");
+            Ok((None, page))
+        }
+    }
+}
+
+async fn get_court_data(
+    url_template: &str,
+    concurrency: usize,
+    url_name: &str,
+    cache: &PageCache,
+) -> Result<CourtScrape, Error> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?;
+
+    let url = get_url(url_template, url_name);
+    log::info!("Get site {url}");
+    // always needed to know the dates, so the request is unconditional
+    let (html, page) = match super::fetch(&client, &url, None).await? {
+        Fetched::Changed(html, page) => (html, page),
+        // not expected without a cached page, but no content either
+        Fetched::Unchanged(_) => return Err(Error::HttpStatus(StatusCode::NOT_MODIFIED)),
+    };
+    let IndexPageContent { full_name, urls } = parse_index_page(&html, &url)?;
+
+    let index_unchanged = cache
+        .get(&url)
+        .is_some_and(|cached| cached.content_hash == page.content_hash);
+    let num_dates = urls.len();
+    let mut pages = vec![(url, page)];
+
+    // `buffered` keeps the results in date order
+    let results: Vec<_> = stream::iter(urls)
+        .map(|(date, url)| {
+            let client = &client;
+            async move {
+                let result = get_sessions(&url, date, client, cache.get(&url)).await;
+                (date, url, result)
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let mut sessions = Vec::new();
+    let mut failed_dates = Vec::new();
+    let mut unchanged_dates = Vec::new();
+    for (date, url, result) in results {
+        match result {
+            Ok((Some(entries), page)) => {
+                sessions.extend(entries);
+                pages.push((url, page));
+            }
+            Ok((None, page)) => {
+                unchanged_dates.push(date);
+                pages.push((url, page));
+            }
+            // the other pages most likely won't be understood either
+            Err(e) if e.is_content_error() => return Err(e),
+            Err(e) => {
+                log::warn!("{url_name}: Failed to get sessions for {date}: {e}");
+                failed_dates.push(date);
+                if let Some(cached) = cache.get(&url) {
+                    pages.push((url, cached.clone()));
+                }
+            }
+        }
+    }
+
+    let unchanged = index_unchanged && unchanged_dates.len() == num_dates;
+    let data = CourtData {
+        full_name,
+        sessions,
+    };
+    Ok(CourtScrape {
+        data,
+        failed_dates,
+        unchanged_dates,
+        unchanged,
+        pages,
+    })
+}
+
+lazy_static! {
+    static ref COURT_NAME_REGEX: Regex = Regex::new("^[a-zA-Z0-9\\-]{1,63}$").unwrap();
+}
+
+/// Courts are identified by the subdomain of their website, e.g. "vg-koeln".
+pub struct NrwJustiz {
+    /// URL of a court's index page, "{name}" is replaced by the court id
+    url_template: String,
+    /// Maximum number of pages fetched at the same time for one court
+    concurrency: usize,
+}
+
+impl NrwJustiz {
+    /// The URL template can be overridden with `NRW_URL_TEMPLATE`, e.g. to
+    /// use a mirror or a local mock server, the concurrency limit with
+    /// `NRW_CONCURRENCY`.
+    pub fn from_env() -> Self {
+        let url_template = match std::env::var("NRW_URL_TEMPLATE") {
+            Ok(template) => {
+                if !template.contains("{name}") {
+                    log::warn!(
+                        "NRW_URL_TEMPLATE does not contain {{name}}, all courts will share one URL"
+                    );
+                }
+                log::info!("Using URL template {template} for NRW courts");
+                template
+            }
+            Err(_) => DEFAULT_URL_TEMPLATE.to_string(),
+        };
+
+        let concurrency = std::env::var("NRW_CONCURRENCY")
+            .ok()
+            .and_then(|n| match n.parse() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    log::warn!("Invalid NRW_CONCURRENCY {n}, using default");
+                    None
+                }
+            })
+            .unwrap_or(DEFAULT_CONCURRENCY);
+
+        Self {
+            url_template,
+            concurrency,
+        }
+    }
+}
+
+impl CourtSource for NrwJustiz {
+    fn is_valid_court(&self, court: &str) -> bool {
+        COURT_NAME_REGEX.is_match(court)
+    }
+
+    fn get_court_data<'a>(
+        &'a self,
+        court: &'a str,
+        cache: &'a PageCache,
+    ) -> BoxFuture<'a, Result<CourtScrape, Error>> {
+        Box::pin(get_court_data(
+            &self.url_template,
+            self.concurrency,
+            court,
+            cache,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,140 @@
+//! Runs the parser on the pages in `tests/fixtures/nrw`. Each directory
+//! contains an `index.html`, one `<date>.html` per offered date and the
+//! expected result in `expected.json`. Run with `UPDATE_SNAPSHOTS=1` to
+//! rewrite the snapshots after an intended change, and review the diff.
+//!
+//! The pages are synthetic: they were written by hand after the markup of
+//! the portal and only contain the parts the parser relies on, not captures
+//! of real pages. When the portal is redesigned, add saved pages as a new
+//! case.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+const INDEX_URL: &str = "https://www.vg-koeln.nrw.de/behoerde/sitzungstermine/index.php";
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nrw")
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+}
+
+fn scrape_fixture(dir: &Path) -> CourtData {
+    let index = parse_index_page(&read(&dir.join("index.html")), INDEX_URL).unwrap();
+
+    let sessions = index
+        .urls
+        .iter()
+        .flat_map(|(date, _)| parse_table(&read(&dir.join(format!("{date}.html"))), *date).unwrap())
+        .collect();
+
+    CourtData {
+        full_name: index.full_name,
+        sessions,
+    }
+}
+
+fn check_fixture(name: &str) {
+    let dir = fixtures_dir().join(name);
+    let data = scrape_fixture(&dir);
+    let snapshot = dir.join("expected.json");
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        let json = serde_json::to_string_pretty(&data).unwrap();
+        fs::write(&snapshot, json + "\n").unwrap();
+        return;
+    }
+
+    let expected: CourtData = serde_json::from_str(&read(&snapshot)).unwrap();
+    assert_eq!(data, expected, "fixture {name} doesn't match its snapshot");
+}
+
+#[test]
+fn empty_court() {
+    check_fixture("empty-court");
+}
+
+#[test]
+fn many_dates() {
+    check_fixture("many-dates");
+}
+
+#[test]
+fn missing_cells() {
+    check_fixture("missing-cells");
+}
+
+#[test]
+fn odd_whitespace() {
+    check_fixture("odd-whitespace");
+}
+
+#[test]
+fn every_fixture_is_tested() {
+    let mut names: Vec<_> = fs::read_dir(fixtures_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+
+    assert_eq!(
+        names,
+        [
+            "empty-court",
+            "many-dates",
+            "missing-cells",
+            "odd-whitespace"
+        ]
+    );
+}
+
+#[test]
+fn date_urls() {
+    let index = read(&fixtures_dir().join("many-dates/index.html"));
+    let index = parse_index_page(&index, INDEX_URL).unwrap();
+
+    let (date, url) = &index.urls[0];
+    assert_eq!(*date, NaiveDate::from_ymd_opt(2024, 9, 2).unwrap());
+    assert_eq!(
+        url,
+        &format!("{INDEX_URL}?startDate=1725228000&termsPerPage=0")
+    );
+
+    assert_eq!(
+        get_date_url("http://localhost:8080/?court=vg-koeln", 1725228000),
+        "http://localhost:8080/?court=vg-koeln&startDate=1725228000&termsPerPage=0"
+    );
+}
+
+#[test]
+fn missing_meta_tag() {
+    let html = "<html><head></head><body><select id=\"startDate\"></select></body></html>";
+    assert!(matches!(
+        parse_index_page(html, INDEX_URL),
+        Err(Error::Parse(_))
+    ));
+}
+
+#[test]
+fn missing_date_selection() {
+    let html = "<html><head><meta name=\"Copyright\" content=\"VG Köln\"></head></html>";
+    assert!(matches!(
+        parse_index_page(html, INDEX_URL),
+        Err(Error::Parse(_))
+    ));
+}
+
+#[test]
+fn redesigned_table() {
+    // must not look like a day without sessions
+    let html =
+        "<table class=\"termine\"><tr id=\"t1\"><td>09:30</td><td>1 K 1/24</td></tr></table>";
+    assert!(matches!(
+        parse_table(html, NaiveDate::from_ymd_opt(2024, 9, 2).unwrap()),
+        Err(Error::Parse(_))
+    ));
+}
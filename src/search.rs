@@ -0,0 +1,135 @@
+//! Searching the sessions of all courts by reference or keyword
+
+use crate::database::CourtSession;
+use crate::directory::normalize;
+use crate::reference::ReferenceFilter;
+use crate::scraper::Session;
+
+/// Matches sessions whose reference matches the query as a reference
+/// pattern, or that contain all of its words in the reference, lawsuit,
+/// type or note.
+pub struct SessionSearch {
+    reference: ReferenceFilter,
+    words: Vec<String>,
+}
+
+impl SessionSearch {
+    pub fn new(query: &str) -> Self {
+        Self {
+            reference: ReferenceFilter::new(query),
+            words: normalize(query)
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+
+    pub fn matches(&self, session: &Session) -> bool {
+        if self.reference.matches(&session.reference) {
+            return true;
+        }
+
+        let text = normalize(&format!(
+            "{} {} {} {}",
+            session.reference, session.lawsuit, session.r#type, session.note
+        ));
+        !self.words.is_empty() && self.words.iter().all(|word| text.contains(word.as_str()))
+    }
+}
+
+/// Whether the query contains anything to search for, which punctuation
+/// alone is not.
+pub fn is_valid_query(query: &str) -> bool {
+    !normalize(query).is_empty()
+}
+
+/// Sessions of one court
+pub struct CourtResults {
+    pub court: String,
+    pub full_name: String,
+    pub sessions: Vec<Session>,
+}
+
+/// Groups the matching sessions by court, keeping their order.
+pub fn search(query: &str, sessions: Vec<CourtSession>) -> Vec<CourtResults> {
+    let search = SessionSearch::new(query);
+    let mut results: Vec<CourtResults> = vec![];
+
+    for CourtSession {
+        court,
+        full_name,
+        session,
+    } in sessions
+    {
+        if !search.matches(&session) {
+            continue;
+        }
+
+        match results.last_mut() {
+            Some(last) if last.court == court => last.sessions.push(session),
+            _ => results.push(CourtResults {
+                full_name: full_name.unwrap_or_else(|| court.clone()),
+                court,
+                sessions: vec![session],
+            }),
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn session(court: &str, reference: &str, lawsuit: &str) -> CourtSession {
+        CourtSession {
+            court: court.to_string(),
+            full_name: None,
+            session: Session {
+                date: NaiveDate::from_ymd_opt(2024, 9, 2).unwrap(),
+                time: String::new(),
+                start_time: None,
+                end_time: None,
+                r#type: "Mündliche Verhandlung".to_string(),
+                lawsuit: lawsuit.to_string(),
+                hall: String::new(),
+                reference: reference.to_string(),
+                note: String::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn grouped_by_court() {
+        let sessions = vec![
+            session("lg-bonn", "1 O 99/24", "Müller ./. Schmidt"),
+            session("vg-koeln", "6 K 1234/23", "Müller ./. Stadt Köln"),
+            session("vg-koeln", "6 K 17/24", "Meyer ./. Land NRW"),
+        ];
+
+        let results = search("Müller", sessions);
+        let courts: Vec<_> = results.iter().map(|r| r.court.as_str()).collect();
+        assert_eq!(courts, ["lg-bonn", "vg-koeln"]);
+        assert_eq!(results[1].full_name, "vg-koeln");
+        assert_eq!(results[1].sessions.len(), 1);
+    }
+
+    #[test]
+    fn without_words() {
+        assert!(!is_valid_query(". /"));
+        assert!(is_valid_query("6 K"));
+
+        let sessions = vec![session("vg-koeln", "6 K 1234/23", "Müller ./. Stadt Köln")];
+        assert!(search(".", sessions).is_empty());
+    }
+
+    #[test]
+    fn by_reference() {
+        let search = SessionSearch::new("6K 1234/23");
+        assert!(search.matches(&session("vg-koeln", "6 K 1234/23", "").session));
+        assert!(!search.matches(&session("vg-koeln", "6 K 17/24", "").session));
+    }
+}
@@ -0,0 +1,325 @@
+//! Guides through /subscribe without arguments: first the court, then the
+//! reference pattern with a preview of the matching sessions, then the name.
+
+use std::sync::Arc;
+
+use teloxide::dispatching::dialogue::{Dialogue, InMemStorage};
+use teloxide::prelude::*;
+use teloxide::types::{ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, ReplyParameters};
+use tokio::sync::Mutex;
+
+use crate::courts::Courts;
+use crate::database::Database;
+use crate::directory::{self, Candidate};
+use crate::messages::{self, MarkdownString};
+use crate::reference::ReferenceFilter;
+use crate::{court_choice, send_chain, Bot};
+
+const CALLBACK_PREFIX: &str = "subscribe:";
+const MAX_REFERENCE_LEN: usize = 100;
+
+#[derive(Debug, Clone, Default)]
+pub enum State {
+    /// No /subscribe in progress
+    #[default]
+    Idle,
+    Court,
+    /// Several courts matched, one is chosen with the buttons
+    ChooseCourt {
+        candidates: Vec<Candidate>,
+    },
+    Reference {
+        court: String,
+    },
+    Name {
+        court: String,
+        reference: String,
+    },
+}
+
+impl State {
+    pub fn is_active(&self) -> bool {
+        !matches!(self, State::Idle)
+    }
+}
+
+pub type Storage = InMemStorage<State>;
+type SubscribeDialogue = Dialogue<State, Storage>;
+
+pub fn is_dialogue_callback(callback_data: &str) -> bool {
+    callback_data.starts_with(CALLBACK_PREFIX)
+}
+
+async fn set_state(dialogue: &SubscribeDialogue, state: State) {
+    if let Err(e) = dialogue.update(state).await {
+        log::error!("Dialogue error: {e}");
+    }
+}
+
+async fn send(
+    bot: &Bot,
+    msg: &Message,
+    text: MarkdownString,
+    force_reply: bool,
+) -> ResponseResult<Message> {
+    let mut request = bot
+        .send_message(msg.chat.id, text.to_string())
+        .reply_parameters(ReplyParameters::new(msg.id));
+    // needed for the answer to reach the bot in groups with privacy mode
+    if force_reply {
+        request = request.reply_markup(ForceReply::new());
+    }
+    request.await
+}
+
+/// Starts the dialogue in the chat of `msg`.
+pub async fn start(bot: &Bot, msg: &Message, storage: Arc<Storage>) -> ResponseResult<()> {
+    let dialogue = SubscribeDialogue::new(storage, msg.chat.id);
+    set_state(&dialogue, State::Court).await;
+    send(bot, msg, messages::ask_court(), true).await?;
+    Ok(())
+}
+
+/// Handles /cancel
+pub async fn cancel(bot: &Bot, msg: &Message, storage: Arc<Storage>) -> ResponseResult<()> {
+    let dialogue = SubscribeDialogue::new(storage, msg.chat.id);
+    let active = matches!(dialogue.get().await, Ok(Some(state)) if state.is_active());
+    if active {
+        set_state(&dialogue, State::Idle).await;
+    }
+    send(bot, msg, messages::subscribe_cancelled(active), false).await?;
+    Ok(())
+}
+
+async fn choose_court(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &SubscribeDialogue,
+    database: &Database,
+    input: &str,
+) -> ResponseResult<()> {
+    let known = match database.get_court_names().await {
+        Ok(known) => known,
+        Err(e) => {
+            log::error!("Database error: {e}");
+            send(bot, msg, messages::internal_error(), false).await?;
+            return Ok(());
+        }
+    };
+
+    // subscriptions are limited to the courts of the directory
+    let mut candidates = directory::resolve(input, &known);
+    candidates.retain(|candidate| directory::get(&candidate.id).is_some());
+
+    match candidates.len() {
+        0 => {
+            let suggestions = directory::suggest(input);
+            send(bot, msg, messages::unknown_court(input, &suggestions), true).await?;
+        }
+        1 => {
+            let court = candidates.pop().unwrap();
+            ask_reference(bot, msg, dialogue, court).await?;
+        }
+        n if n > court_choice::MAX_CHOICES => {
+            send(bot, msg, messages::court_too_ambiguous(input), true).await?;
+        }
+        _ => {
+            let buttons = candidates.iter().enumerate().map(|(index, candidate)| {
+                let data = format!("{CALLBACK_PREFIX}court:{index}");
+                [InlineKeyboardButton::callback(&candidate.full_name, data)]
+            });
+            bot.send_message(msg.chat.id, messages::choose_court(input).to_string())
+                .reply_parameters(ReplyParameters::new(msg.id))
+                .reply_markup(InlineKeyboardMarkup::new(buttons))
+                .await?;
+            set_state(dialogue, State::ChooseCourt { candidates }).await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn ask_reference(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &SubscribeDialogue,
+    court: Candidate,
+) -> ResponseResult<()> {
+    set_state(dialogue, State::Reference { court: court.id }).await;
+    send(bot, msg, messages::ask_reference(&court.full_name), true).await?;
+    Ok(())
+}
+
+async fn preview_and_ask_name(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &SubscribeDialogue,
+    database: &Database,
+    court: String,
+    reference: &str,
+) -> ResponseResult<()> {
+    let meta = database.get_court_meta(&court).await;
+    let sessions = database.get_sessions(&court, None).await;
+    let sessions = match (meta, sessions) {
+        (Ok(None), _) => None,
+        (Ok(Some(_)), Ok(sessions)) => Some(sessions),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Database error: {e}");
+            send(bot, msg, messages::internal_error(), false).await?;
+            return Ok(());
+        }
+    };
+
+    let today = chrono::Utc::now()
+        .with_timezone(&chrono_tz::Europe::Berlin)
+        .date_naive();
+    let filter = ReferenceFilter::new(reference);
+    let matching = sessions.map(|sessions| {
+        sessions
+            .into_iter()
+            .filter(|session| session.date >= today && filter.matches(&session.reference))
+            .collect::<Vec<_>>()
+    });
+
+    let text = messages::reference_preview(reference, matching.as_deref());
+    let default_name = InlineKeyboardButton::callback(
+        format!("„{reference}” als Namen verwenden"),
+        format!("{CALLBACK_PREFIX}name"),
+    );
+    bot.send_message(msg.chat.id, text.to_string())
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .reply_markup(InlineKeyboardMarkup::new([[default_name]]))
+        .await?;
+
+    let reference = reference.to_string();
+    set_state(dialogue, State::Name { court, reference }).await;
+    Ok(())
+}
+
+/// Stores the subscription and returns whether the dialogue is over, which
+/// it is not if the name is taken.
+async fn subscribe(
+    bot: &Bot,
+    msg: &Message,
+    courts: &Mutex<Courts>,
+    database: &Database,
+    court: &str,
+    reference: &str,
+    name: &str,
+) -> ResponseResult<bool> {
+    let subscription_id = match database
+        .add_subscription(msg.chat.id, court, name, reference)
+        .await
+    {
+        Ok(Some(subscription_id)) => subscription_id,
+        Ok(None) => {
+            send(bot, msg, messages::subscription_exists(name), true).await?;
+            return Ok(false);
+        }
+        Err(e) => {
+            log::error!("Database error: {e}");
+            send(bot, msg, messages::internal_error(), false).await?;
+            return Ok(true);
+        }
+    };
+
+    let (bot, database, chat_id) = (bot.clone(), database.clone(), msg.chat.id);
+    match courts.lock().await.get(court) {
+        Ok(mut court) => court.confirm_subscription(subscription_id, move |reply| async move {
+            send_chain(&bot, &database, chat_id, reply).await
+        }),
+        Err(_) => log::error!("Invalid court {court} in subscribe dialogue"),
+    }
+    Ok(true)
+}
+
+/// Handles an answer to one of the questions.
+pub async fn handle_message(
+    bot: Bot,
+    msg: Message,
+    dialogue: SubscribeDialogue,
+    state: State,
+    courts: Arc<Mutex<Courts>>,
+    database: Database,
+) -> ResponseResult<()> {
+    let Some(text) = msg.text().map(str::trim).filter(|text| !text.is_empty()) else {
+        send(&bot, &msg, messages::text_expected(), true).await?;
+        return Ok(());
+    };
+
+    match state {
+        State::Idle => (),
+        State::Court | State::ChooseCourt { .. } => {
+            choose_court(&bot, &msg, &dialogue, &database, text).await?
+        }
+        State::Reference { court } => {
+            if text.chars().count() > MAX_REFERENCE_LEN {
+                send(&bot, &msg, messages::invalid_reference(), true).await?;
+            } else {
+                preview_and_ask_name(&bot, &msg, &dialogue, &database, court, text).await?
+            }
+        }
+        State::Name { court, reference } => {
+            if subscribe(&bot, &msg, &courts, &database, &court, &reference, text).await? {
+                set_state(&dialogue, State::Idle).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles the buttons to choose the court and to use the default name.
+pub async fn handle_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    storage: Arc<Storage>,
+    courts: Arc<Mutex<Courts>>,
+    database: Database,
+) -> ResponseResult<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    let (Some(data), Some(message)) = (&query.data, query.regular_message()) else {
+        return Ok(());
+    };
+    let dialogue = SubscribeDialogue::new(storage, message.chat.id);
+    let state = dialogue.get().await.ok().flatten().unwrap_or_default();
+    let action = data.strip_prefix(CALLBACK_PREFIX).unwrap_or_default();
+
+    match (state, action.strip_prefix("court:")) {
+        (State::ChooseCourt { candidates }, Some(index)) => {
+            let Some(court) = index
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| candidates.into_iter().nth(index))
+            else {
+                return Ok(());
+            };
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                messages::court_chosen(&court.full_name).to_string(),
+            )
+            .await?;
+            ask_reference(&bot, message, &dialogue, court).await
+        }
+        (State::Name { court, reference }, None) if action == "name" => {
+            bot.edit_message_reply_markup(message.chat.id, message.id)
+                .await?;
+            if subscribe(
+                &bot, message, &courts, &database, &court, &reference, &reference,
+            )
+            .await?
+            {
+                set_state(&dialogue, State::Idle).await;
+            }
+            Ok(())
+        }
+        // the dialogue moved on or was cancelled
+        _ => {
+            bot.edit_message_reply_markup(message.chat.id, message.id)
+                .await?;
+            Ok(())
+        }
+    }
+}
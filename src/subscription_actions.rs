@@ -0,0 +1,129 @@
+//! Buttons below the entries of /list_subscriptions and the rename prompts
+//! they open.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageId};
+use tokio::sync::Mutex;
+
+use crate::database::Subscription;
+
+const CALLBACK_PREFIX: &str = "sub:";
+/// How long a rename prompt waits for the reply
+const RENAME_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    ShowSessions,
+    Pause,
+    Resume,
+    /// Asks whether to really delete
+    Delete,
+    ConfirmDelete,
+    CancelDelete,
+    Rename,
+}
+
+const ACTIONS: [(Action, &str); 7] = [
+    (Action::ShowSessions, "show"),
+    (Action::Pause, "pause"),
+    (Action::Resume, "resume"),
+    (Action::Delete, "delete"),
+    (Action::ConfirmDelete, "confirm"),
+    (Action::CancelDelete, "cancel"),
+    (Action::Rename, "rename"),
+];
+
+fn callback_data(action: Action, subscription_id: i64) -> String {
+    let (_, name) = ACTIONS.iter().find(|(a, _)| *a == action).unwrap();
+    format!("{CALLBACK_PREFIX}{name}:{subscription_id}")
+}
+
+pub fn is_subscription_action(callback_data: &str) -> bool {
+    callback_data.starts_with(CALLBACK_PREFIX)
+}
+
+/// Returns the action and the id of the subscription it applies to.
+pub fn parse(callback_data: &str) -> Option<(Action, i64)> {
+    let (name, id) = callback_data
+        .strip_prefix(CALLBACK_PREFIX)?
+        .split_once(':')?;
+    let (action, _) = ACTIONS.iter().find(|(_, n)| *n == name)?;
+    Some((*action, id.parse().ok()?))
+}
+
+fn button(text: &str, action: Action, subscription_id: i64) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, callback_data(action, subscription_id))
+}
+
+pub fn keyboard(sub: &Subscription) -> InlineKeyboardMarkup {
+    let id = sub.subscription_id;
+    let pause = if sub.paused != 0 {
+        button("Fortsetzen", Action::Resume, id)
+    } else {
+        button("Pausieren", Action::Pause, id)
+    };
+
+    InlineKeyboardMarkup::new([
+        [button("Termine anzeigen", Action::ShowSessions, id), pause],
+        [
+            button("Löschen", Action::Delete, id),
+            button("Umbenennen", Action::Rename, id),
+        ],
+    ])
+}
+
+pub fn confirm_delete_keyboard(subscription_id: i64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        button("Ja, löschen", Action::ConfirmDelete, subscription_id),
+        button("Abbrechen", Action::CancelDelete, subscription_id),
+    ]])
+}
+
+struct PendingRename {
+    subscription_id: i64,
+    created: Instant,
+}
+
+/// Rename prompts waiting for a reply, by chat and message id of the prompt
+#[derive(Clone, Default)]
+pub struct PendingRenames(Arc<Mutex<HashMap<(ChatId, MessageId), PendingRename>>>);
+
+impl PendingRenames {
+    pub async fn add(&self, prompt: &Message, subscription_id: i64) {
+        let mut pending = self.0.lock().await;
+        pending.retain(|_, rename| rename.created.elapsed() < RENAME_TIMEOUT);
+        pending.insert(
+            (prompt.chat.id, prompt.id),
+            PendingRename {
+                subscription_id,
+                created: Instant::now(),
+            },
+        );
+    }
+
+    /// Returns the subscription to rename if `msg` answers a rename prompt.
+    pub async fn take(&self, msg: &Message) -> Option<i64> {
+        let prompt = msg.reply_to_message()?;
+        let rename = self.0.lock().await.remove(&(msg.chat.id, prompt.id))?;
+        (rename.created.elapsed() < RENAME_TIMEOUT).then_some(rename.subscription_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_data_round_trip() {
+        for (action, _) in ACTIONS {
+            let data = callback_data(action, 42);
+            assert!(is_subscription_action(&data));
+            assert_eq!(parse(&data), Some((action, 42)));
+        }
+        assert_eq!(parse("sub:explode:42"), None);
+        assert_eq!(parse("court:1:2"), None);
+    }
+}